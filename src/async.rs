@@ -1,16 +1,217 @@
 //! Wrappers around `rados_completion_t`, providing a safe, futures-based API
 //! for asynchronous RADOS operations.
+//!
+//! Every future and stream in this library is written once, against the `RadosFuture` and
+//! `RadosStream` traits below, and then exposed through `std::future::Future` (or the
+//! `futures-core` `Stream` trait) by the `rados_future!` and `rados_stream!` macros. With the
+//! `futures-01` feature enabled, the same macros also implement the `futures` 0.1 `Future` and
+//! `Stream` traits.
 
+use std::fmt;
+use std::future::Future;
 use std::mem;
+use std::pin::Pin;
 use std::ptr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::task::{self, Poll, Waker};
+use std::thread::{self, Thread};
 
 use ceph::rados::{self, rados_completion_t};
-use futures::{Async, Future, Poll};
-use futures::task::AtomicTask;
+#[cfg(feature = "futures-01")]
+use futures;
 use libc;
 
-use errors::{self, Error, Result};
+use errors::{self, Result};
+use ffi;
+
+/// Unwrap a `Poll<Result<T>>`, returning early if it is pending or has failed.
+macro_rules! try_ready {
+    ($e:expr) => {
+        match $e {
+            ::std::task::Poll::Ready(Ok(item)) => item,
+            ::std::task::Poll::Ready(Err(error)) => {
+                return ::std::task::Poll::Ready(Err(From::from(error)))
+            }
+            ::std::task::Poll::Pending => return ::std::task::Poll::Pending,
+        }
+    };
+}
+
+/// Implement `std::future::Future`, and with the `futures-01` feature the `futures` 0.1
+/// `Future`, for a type implementing `RadosFuture`. Generic parameters go in the brackets, and
+/// any bounds on them in a trailing `where` clause.
+macro_rules! rados_future {
+    ([$($gen:tt)*] $ty:ty $(where $($bound:tt)*)*) => {
+        // None of our futures are self-referential, so they may be moved freely.
+        impl<$($gen)*> ::std::marker::Unpin for $ty $(where $($bound)*)* {}
+
+        impl<$($gen)*> ::std::future::Future for $ty $(where $($bound)*)* {
+            type Output = ::errors::Result<<$ty as ::async::RadosFuture>::Item>;
+
+            fn poll(
+                self: ::std::pin::Pin<&mut Self>,
+                cx: &mut ::std::task::Context,
+            ) -> ::std::task::Poll<Self::Output> {
+                let wake = ::async::Wake::Std(cx.waker());
+
+                ::async::RadosFuture::poll_rados(self.get_mut(), &wake)
+            }
+        }
+
+        #[cfg(feature = "futures-01")]
+        impl<$($gen)*> ::futures::Future for $ty $(where $($bound)*)* {
+            type Item = <$ty as ::async::RadosFuture>::Item;
+            type Error = ::errors::Error;
+
+            fn poll(&mut self) -> ::futures::Poll<Self::Item, Self::Error> {
+                match ::async::RadosFuture::poll_rados(self, &::async::Wake::Current) {
+                    ::std::task::Poll::Ready(Ok(item)) => Ok(::futures::Async::Ready(item)),
+                    ::std::task::Poll::Ready(Err(error)) => Err(error),
+                    ::std::task::Poll::Pending => Ok(::futures::Async::NotReady),
+                }
+            }
+        }
+    };
+}
+
+/// Implement the `futures-core` `Stream`, and with the `futures-01` feature the `futures` 0.1
+/// `Stream`, for a type implementing `RadosStream`.
+macro_rules! rados_stream {
+    ($ty:ty) => {
+        impl ::std::marker::Unpin for $ty {}
+
+        impl ::futures_core::Stream for $ty {
+            type Item = ::errors::Result<<$ty as ::async::RadosStream>::Item>;
+
+            fn poll_next(
+                self: ::std::pin::Pin<&mut Self>,
+                cx: &mut ::std::task::Context,
+            ) -> ::std::task::Poll<Option<Self::Item>> {
+                let wake = ::async::Wake::Std(cx.waker());
+
+                ::async::RadosStream::poll_next_rados(self.get_mut(), &wake)
+            }
+        }
+
+        #[cfg(feature = "futures-01")]
+        impl ::futures::Stream for $ty {
+            type Item = <$ty as ::async::RadosStream>::Item;
+            type Error = ::errors::Error;
+
+            fn poll(&mut self) -> ::futures::Poll<Option<Self::Item>, Self::Error> {
+                match ::async::RadosStream::poll_next_rados(self, &::async::Wake::Current) {
+                    ::std::task::Poll::Ready(Some(Ok(item))) => {
+                        Ok(::futures::Async::Ready(Some(item)))
+                    }
+                    ::std::task::Poll::Ready(Some(Err(error))) => Err(error),
+                    ::std::task::Poll::Ready(None) => Ok(::futures::Async::Ready(None)),
+                    ::std::task::Poll::Pending => Ok(::futures::Async::NotReady),
+                }
+            }
+        }
+    };
+}
+
+/// How to wake the task currently polling a future: either through a `std::task::Waker`, or,
+/// with the `futures-01` feature, through the current `futures` 0.1 task.
+pub enum Wake<'a> {
+    Std(&'a Waker),
+
+    #[cfg(feature = "futures-01")]
+    Current,
+}
+
+/// A registered task, waiting to be woken.
+#[derive(Debug)]
+enum Waiter {
+    Std(Waker),
+
+    #[cfg(feature = "futures-01")]
+    Task(futures::task::Task),
+}
+
+/// A slot holding the task waiting on some asynchronous event, which may be woken from any thread.
+/// This does the job of `futures` 0.1's `AtomicTask`, for both kinds of task.
+#[derive(Debug, Default)]
+pub struct WakerSlot {
+    waiter: Mutex<Option<Waiter>>,
+}
+
+impl WakerSlot {
+    pub fn new() -> WakerSlot {
+        WakerSlot::default()
+    }
+
+    /// Register the polling task to be woken. This must be done *before* a consumer checks for
+    /// produced data, so that data produced in between cannot be missed.
+    pub fn register(&self, wake: &Wake) {
+        let waiter = match *wake {
+            Wake::Std(waker) => Waiter::Std(waker.clone()),
+
+            #[cfg(feature = "futures-01")]
+            Wake::Current => Waiter::Task(futures::task::current()),
+        };
+
+        *self.waiter.lock().unwrap() = Some(waiter);
+    }
+
+    /// Wake the registered task, if any. This should be done *after* data is produced.
+    pub fn wake(&self) {
+        let waiter = self.waiter.lock().unwrap().take();
+
+        match waiter {
+            Some(Waiter::Std(waker)) => waker.wake(),
+
+            #[cfg(feature = "futures-01")]
+            Some(Waiter::Task(task)) => task.notify(),
+
+            None => {}
+        }
+    }
+}
+
+/// Wakes a thread blocked in `block_on`.
+struct ThreadWaker(Thread);
+
+impl task::Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Block the current thread until a future completes. This is for the synchronous operations
+/// which are built out of several asynchronous ones, such as those on striped objects.
+pub fn block_on<F>(mut future: F) -> F::Output
+where
+    F: Future + Unpin,
+{
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = task::Context::from_waker(&waker);
+
+    loop {
+        if let Poll::Ready(output) = Pin::new(&mut future).poll(&mut cx) {
+            return output;
+        }
+
+        thread::park();
+    }
+}
+
+/// A future, in a form which can be exposed through both `std::future::Future` and `futures`
+/// 0.1's `Future` by `rados_future!`.
+pub trait RadosFuture {
+    type Item;
+
+    fn poll_rados(&mut self, wake: &Wake) -> Poll<Result<Self::Item>>;
+}
+
+/// A stream, in a form which can be exposed through both `futures-core`'s `Stream` and `futures`
+/// 0.1's `Stream` by `rados_stream!`.
+pub trait RadosStream {
+    type Item;
+
+    fn poll_next_rados(&mut self, wake: &Wake) -> Poll<Option<Result<Self::Item>>>;
+}
 
 /// The result of a `Completion`'s successful execution.
 #[derive(Debug)]
@@ -20,38 +221,48 @@ pub struct Return<T> {
 
     /// The non-error return value of the RADOS completion.
     pub value: u32,
+
+    /// The version of the object operated on, as reported by `rados_aio_get_version`.
+    pub version: u64,
 }
 
 /// The info struct passed into a RADOS callback, providing a trigger to potentially deallocate
-/// associated data and also an `AtomicTask` object for notifying all registered tasks.
+/// associated data and also a `WakerSlot` for waking the waiting task.
 struct CompletionInfo<T> {
-    task: Arc<AtomicTask>,
+    waker: Arc<WakerSlot>,
     data: Arc<T>,
 }
 
 /// The callback passed into librados, and called on future completion.
 extern "C" fn callback<T>(_handle: rados_completion_t, info_ptr: *mut libc::c_void) {
-    let CompletionInfo { task, data } =
+    let CompletionInfo { waker, data } =
         *unsafe { Box::from_raw(info_ptr as *mut CompletionInfo<T>) };
 
     // Allow a poll to unwrap the contained data.
     mem::drop(data);
 
-    // `AtomicTask` should be notified *after* data is produced. Data is produced, here, by
-    // reducing the strong reference count of the `data: Arc<T>` to `1`, and thus allowing a
-    // successful `.poll()` to `Arc::try_unwrap()` the data.
-    task.notify();
+    // The task should be woken *after* data is produced. Data is produced, here, by reducing the
+    // strong reference count of the `data: Arc<T>` to `1`, and thus allowing a successful poll to
+    // `Arc::try_unwrap()` the data.
+    waker.wake();
 }
 
 /// The type of a wrapped `rados_completion_t`, with associated allocated custom data and
-/// `AtomicTask`. This is a bare-metal `RadosFuture`.
-#[derive(Debug)]
+/// `WakerSlot`. This is a bare-metal `RadosFuture`.
 pub struct Completion<T> {
-    task: Arc<AtomicTask>,
+    waker: Arc<WakerSlot>,
     data: Option<Arc<T>>,
     handle: rados_completion_t,
 }
 
+impl<T> fmt::Debug for Completion<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Completion")
+            .field("handle", &self.handle)
+            .finish()
+    }
+}
+
 impl<T> Completion<T> {
     /// Construct a new `Completion` from a piece of data and an initialization function. The
     /// initialization function takes in a `rados_completion_t` and is intended to call a
@@ -63,11 +274,11 @@ impl<T> Completion<T> {
     {
         let mut completion_handle = ptr::null_mut();
 
-        let task = Arc::new(AtomicTask::new());
+        let waker = Arc::new(WakerSlot::new());
         let data = Arc::new(data);
 
         let info_ptr = Box::into_raw(Box::new(CompletionInfo {
-            task: task.clone(),
+            waker: waker.clone(),
             data: data.clone(),
         }));
 
@@ -86,7 +297,7 @@ impl<T> Completion<T> {
 
         match init(completion_handle) {
             Ok(()) => Ok(Completion {
-                task,
+                waker,
                 data: Some(data),
                 handle: completion_handle,
             }),
@@ -99,29 +310,44 @@ impl<T> Completion<T> {
             }
         }
     }
-}
-
-impl<T> Future for Completion<T> {
-    type Item = Return<T>;
-    type Error = Error;
-
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        // `AtomicTask` should have `.register()` called before a consumer checks for produced data.
-        self.task.register();
 
-        let value =
-            errors::librados_res(unsafe { rados::rados_aio_get_return_value(self.handle) })?;
+    /// Poll the completion, yielding its data alongside its raw return value, *even if* the
+    /// return value indicates an error. This is for operations such as `rados_aio_notify` which
+    /// still produce useful data when they fail.
+    pub fn poll_raw(&mut self, wake: &Wake) -> Poll<(i32, T)> {
+        self.waker.register(wake);
 
         match Arc::try_unwrap(self.data.take().unwrap()) {
-            Ok(data) => Ok(Async::Ready(Return { value, data })),
+            Ok(data) => {
+                let value = unsafe { rados::rados_aio_get_return_value(self.handle) };
+
+                Poll::Ready((value, data))
+            }
             Err(arc) => {
                 self.data = Some(arc);
-                Ok(Async::NotReady)
+                Poll::Pending
             }
         }
     }
 }
 
+impl<T> RadosFuture for Completion<T> {
+    type Item = Return<T>;
+
+    fn poll_rados(&mut self, wake: &Wake) -> Poll<Result<Self::Item>> {
+        self.poll_raw(wake).map(|(value, data)| {
+            let value = errors::librados_res(value)?;
+            let version = unsafe { ffi::rados_aio_get_version(self.handle) };
+
+            Ok(Return {
+                value,
+                data,
+                version,
+            })
+        })
+    }
+}
+
 impl<T> Drop for Completion<T> {
     fn drop(&mut self) {
         unsafe {
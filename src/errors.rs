@@ -1,4 +1,5 @@
 use std::ffi::CStr;
+use std::io;
 
 use libc;
 
@@ -23,16 +24,31 @@ error_chain! {
             description("RADOS error")
             display("RADOS error code {}: `{}`", e, get_error_string(*e).unwrap())
         }
+
+        ExtentMismatch(offset: u64) {
+            description("compared extent does not match")
+            display("compared extent does not match the object at offset {}", offset)
+        }
+
+        ComparisonFailed {
+            description("comparison guard failed")
+            display("comparison guard failed")
+        }
+
+        CommandFailed(e: u32, status: String) {
+            description("cluster command failed")
+            display("cluster command failed with RADOS error code {}: `{}`", e, status)
+        }
     }
 }
 
+/// The largest error number used by librados. An extent comparison which fails at offset `n`
+/// returns `-MAX_ERRNO - n` in place of an error code.
+const MAX_ERRNO: i64 = 4095;
+
 /// Convert the integer output of a librados API function into a `Result<()>`.
 pub fn librados(err: i32) -> Result<()> {
-    if err < 0 {
-        bail!(ErrorKind::Rados(-err as u32));
-    } else {
-        Ok(())
-    }
+    librados_res(err).map(|_| ())
 }
 
 /// Convert the integer output of a librados API function into a `Result<u32>`, returning the error
@@ -45,6 +61,40 @@ pub fn librados_res(err: i32) -> Result<u32> {
     }
 }
 
+/// Convert an error from an extent comparison, or from an operation containing one. librados
+/// reports a mismatch at offset `n` as the error code `MAX_ERRNO + n`, which is turned into
+/// `ErrorKind::ExtentMismatch`; any other error is returned unchanged.
+pub fn cmpext(error: Error) -> Error {
+    match error {
+        Error(ErrorKind::Rados(err_code), _) if err_code as i64 >= MAX_ERRNO => {
+            ErrorKind::ExtentMismatch((err_code as i64 - MAX_ERRNO) as u64).into()
+        }
+        error => error,
+    }
+}
+
+/// Convert an error from an operation containing comparison guards. librados reports a failed
+/// `cmpxattr` or omap comparison as `ECANCELED`, which is turned into
+/// `ErrorKind::ComparisonFailed`; any other error is returned unchanged.
+pub fn comparison(error: Error) -> Error {
+    match error {
+        Error(ErrorKind::Rados(err_code), _) if err_code == libc::ECANCELED as u32 => {
+            ErrorKind::ComparisonFailed.into()
+        }
+        error => error,
+    }
+}
+
+/// Convert an error into an `io::Error`, for implementations of the standard I/O traits. RADOS
+/// error codes become the corresponding OS errors, so that their `io::ErrorKind` is kept.
+pub fn io_error(error: Error) -> io::Error {
+    match error {
+        Error(ErrorKind::Rados(err_code), _) => io::Error::from_raw_os_error(err_code as i32),
+        Error(ErrorKind::IO(error), _) => error,
+        error => io::Error::new(io::ErrorKind::Other, error.to_string()),
+    }
+}
+
 /// Get the registered error string for a given error number.
 pub fn get_error_string(err: u32) -> Result<String> {
     let error = unsafe {
@@ -0,0 +1,234 @@
+//! Raw declarations for librados functions which are not exposed by the version of the `ceph`
+//! crate this library depends on, or which it declares with the wrong signature. Most of these
+//! were added to librados after the `ceph` bindings were generated; the signatures here are taken
+//! directly from `librados.h`.
+
+#![allow(non_camel_case_types)]
+
+use ceph::rados::{rados_completion_t, rados_ioctx_t, rados_list_ctx_t, rados_omap_iter_t,
+                  rados_read_op_t, rados_t, rados_write_op_t};
+use libc::{c_char, c_int, c_long, c_uchar, c_void, size_t, time_t, uint32_t, uint64_t};
+
+/// An opaque handle to a position in a pool listing.
+pub type rados_object_list_cursor = *mut c_void;
+
+/// An entry returned by `rados_object_list`. The strings are *not* NUL-terminated.
+#[repr(C)]
+#[derive(Debug)]
+pub struct rados_object_list_item {
+    pub oid_length: size_t,
+    pub oid: *mut c_char,
+    pub nspace_length: size_t,
+    pub nspace: *mut c_char,
+    pub locator_length: size_t,
+    pub locator: *mut c_char,
+}
+
+/// A `struct timespec`, as filled in by `rados_stat2`. This is declared here rather than taken
+/// from `libc` so that it implements `Debug`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct timespec {
+    pub tv_sec: time_t,
+    pub tv_nsec: c_long,
+}
+
+/// The statistics of a pool, as filled in by `rados_ioctx_pool_stat`. The `ceph` crate declares
+/// this without the last four fields, which librados has since added and always writes.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct rados_pool_stat_t {
+    pub num_bytes: uint64_t,
+    pub num_kb: uint64_t,
+    pub num_objects: uint64_t,
+    pub num_object_clones: uint64_t,
+    pub num_object_copies: uint64_t,
+    pub num_objects_missing_on_primary: uint64_t,
+    pub num_objects_unfound: uint64_t,
+    pub num_objects_degraded: uint64_t,
+    pub num_rd: uint64_t,
+    pub num_rd_kb: uint64_t,
+    pub num_wr: uint64_t,
+    pub num_wr_kb: uint64_t,
+    pub num_user_bytes: uint64_t,
+    pub compressed_bytes_orig: uint64_t,
+    pub compressed_bytes: uint64_t,
+    pub compressed_bytes_alloc: uint64_t,
+}
+
+/// The callback invoked by librados when a watched object receives a notification.
+pub type rados_watchcb2_t = Option<
+    extern "C" fn(
+        arg: *mut c_void,
+        notify_id: uint64_t,
+        handle: uint64_t,
+        notifier_id: uint64_t,
+        data: *mut c_void,
+        data_len: size_t,
+    ),
+>;
+
+/// The callback invoked by librados when a watch encounters an error, such as a disconnection.
+pub type rados_watcherrcb_t = Option<extern "C" fn(pre: *mut c_void, cookie: uint64_t, err: c_int)>;
+
+extern "C" {
+    pub fn rados_ioctx_get_namespace(io: rados_ioctx_t, buf: *mut c_char, maxlen: size_t) -> c_int;
+
+    // The `ceph` crate declares the out-parameters of this as `*mut *mut *const c_char`, which
+    // does not match `const char **` in `librados.h`.
+    pub fn rados_nobjects_list_next(
+        ctx: rados_list_ctx_t,
+        entry: *mut *const c_char,
+        key: *mut *const c_char,
+        nspace: *mut *const c_char,
+    ) -> c_int;
+    pub fn rados_nobjects_list_get_cursor(
+        ctx: rados_list_ctx_t,
+        cursor: *mut rados_object_list_cursor,
+    ) -> c_int;
+    pub fn rados_nobjects_list_seek_cursor(
+        ctx: rados_list_ctx_t,
+        cursor: rados_object_list_cursor,
+    ) -> uint32_t;
+
+    pub fn rados_object_list_begin(io: rados_ioctx_t) -> rados_object_list_cursor;
+    pub fn rados_object_list_end(io: rados_ioctx_t) -> rados_object_list_cursor;
+    pub fn rados_object_list_is_end(io: rados_ioctx_t, cur: rados_object_list_cursor) -> c_int;
+    pub fn rados_object_list_cursor_free(io: rados_ioctx_t, cur: rados_object_list_cursor);
+    pub fn rados_object_list_cursor_cmp(
+        io: rados_ioctx_t,
+        lhs: rados_object_list_cursor,
+        rhs: rados_object_list_cursor,
+    ) -> c_int;
+    pub fn rados_object_list(
+        io: rados_ioctx_t,
+        start: rados_object_list_cursor,
+        finish: rados_object_list_cursor,
+        result_size: size_t,
+        filter_buf: *const c_char,
+        filter_buf_len: size_t,
+        results: *mut rados_object_list_item,
+        next: *mut rados_object_list_cursor,
+    ) -> c_int;
+    pub fn rados_object_list_free(result_size: size_t, results: *mut rados_object_list_item);
+    pub fn rados_object_list_slice(
+        io: rados_ioctx_t,
+        start: rados_object_list_cursor,
+        finish: rados_object_list_cursor,
+        n: size_t,
+        m: size_t,
+        split_start: *mut rados_object_list_cursor,
+        split_finish: *mut rados_object_list_cursor,
+    );
+
+    pub fn rados_read_op_omap_get_vals2(
+        read_op: rados_read_op_t,
+        start_after: *const c_char,
+        filter_prefix: *const c_char,
+        max_return: uint64_t,
+        iter: *mut rados_omap_iter_t,
+        pmore: *mut c_uchar,
+        prval: *mut c_int,
+    );
+    pub fn rados_read_op_omap_get_keys2(
+        read_op: rados_read_op_t,
+        start_after: *const c_char,
+        max_return: uint64_t,
+        iter: *mut rados_omap_iter_t,
+        pmore: *mut c_uchar,
+        prval: *mut c_int,
+    );
+
+    pub fn rados_cmpext(
+        io: rados_ioctx_t,
+        o: *const c_char,
+        cmp_buf: *const c_char,
+        cmp_len: size_t,
+        off: uint64_t,
+    ) -> c_int;
+    pub fn rados_aio_cmpext(
+        io: rados_ioctx_t,
+        o: *const c_char,
+        completion: rados_completion_t,
+        cmp_buf: *const c_char,
+        cmp_len: size_t,
+        off: uint64_t,
+    ) -> c_int;
+    pub fn rados_write_op_cmpext(
+        write_op: rados_write_op_t,
+        cmp_buf: *const c_char,
+        cmp_len: size_t,
+        off: uint64_t,
+        prval: *mut c_int,
+    );
+    pub fn rados_read_op_cmpext(
+        read_op: rados_read_op_t,
+        cmp_buf: *const c_char,
+        cmp_len: size_t,
+        off: uint64_t,
+        prval: *mut c_int,
+    );
+
+    pub fn rados_aio_get_version(c: rados_completion_t) -> uint64_t;
+
+    pub fn rados_watch3(
+        io: rados_ioctx_t,
+        o: *const c_char,
+        cookie: *mut uint64_t,
+        watchcb: rados_watchcb2_t,
+        watcherrcb: rados_watcherrcb_t,
+        timeout: uint32_t,
+        arg: *mut c_void,
+    ) -> c_int;
+    pub fn rados_aio_notify(
+        io: rados_ioctx_t,
+        o: *const c_char,
+        completion: rados_completion_t,
+        buf: *const c_char,
+        buf_len: c_int,
+        timeout_ms: uint64_t,
+        reply_buffer: *mut *mut c_char,
+        reply_buffer_len: *mut size_t,
+    ) -> c_int;
+
+    pub fn rados_aio_ioctx_selfmanaged_snap_create(
+        io: rados_ioctx_t,
+        snapid: *mut uint64_t,
+        completion: rados_completion_t,
+    );
+    pub fn rados_aio_ioctx_selfmanaged_snap_remove(
+        io: rados_ioctx_t,
+        snapid: uint64_t,
+        completion: rados_completion_t,
+    );
+
+    // The `ceph` crate declares this with its outdated `Struct_rados_pool_stat_t`, which is too
+    // small for what librados writes.
+    pub fn rados_ioctx_pool_stat(io: rados_ioctx_t, stats: *mut rados_pool_stat_t) -> c_int;
+
+    pub fn rados_getaddrs(cluster: rados_t, addrs: *mut *mut c_char) -> c_int;
+    pub fn rados_ioctx_pool_requires_alignment2(io: rados_ioctx_t, req: *mut c_int) -> c_int;
+    pub fn rados_ioctx_pool_required_alignment2(
+        io: rados_ioctx_t,
+        alignment: *mut uint64_t,
+    ) -> c_int;
+    pub fn rados_stat2(
+        io: rados_ioctx_t,
+        o: *const c_char,
+        psize: *mut uint64_t,
+        pmtime: *mut timespec,
+    ) -> c_int;
+    pub fn rados_aio_stat2(
+        io: rados_ioctx_t,
+        o: *const c_char,
+        completion: rados_completion_t,
+        psize: *mut uint64_t,
+        pmtime: *mut timespec,
+    ) -> c_int;
+    pub fn rados_read_op_stat2(
+        read_op: rados_read_op_t,
+        psize: *mut uint64_t,
+        pmtime: *mut timespec,
+        prval: *mut c_int,
+    );
+}
@@ -6,12 +6,33 @@
 //! an idiomatic, Rusty fashion, using the futures library for asynchronous
 //! operations.
 //!
-//! Missing functionality includes MON/OSD/PGS commands.
-//!
 //! Current features:
 //! - Read, write, full-write, append
+//! - Buffered, file-like object handles implementing `Read`, `Write` and `Seek`
+//! - Striping large objects over many RADOS objects, compatibly with `libradosstriper`
+//! - Listing the objects in a pool, with resumable cursors, either blocking or as a `Stream`
+//! - Splitting a pool listing into disjoint ranges for parallel listing
+//! - RADOS namespaces, including listing across all namespaces
+//! - Object locator keys
+//! - Object omap reads and writes
+//! - Copying objects, along with their extended attributes and omaps, and renaming objects
+//! - Atomic compound write operations, and compound read operations in a single round trip
+//! - Compare-and-swap guards on object data, extended attributes and omap values
+//! - Object versions, and conditional operations guarded on them
+//! - Object stats with nanosecond modification times, and extended stats in a single round trip
+//! - Watching objects for notifications, and notifying their watchers
+//! - Advisory exclusive and shared object locks
+//! - Pool snapshots, rollback, and reading from a snapshot
+//! - Self-managed snapshots and write snapshot contexts
+//! - Creating, deleting, listing, and looking up pools
+//! - Per-pool usage statistics
+//! - Monitor, OSD and placement group commands
+//! - Cluster, client and pool identity queries, and configuration lookups
 //! - Automatic `rados_shutdown` on drop of the reference-counted `RadosCluster` type
-//! - Asynchronous read/write/etc. using futures
+//! - Asynchronous read/write/etc. using `std::future::Future`, usable with `async`/`await`, or
+//!   with the `futures` 0.1 API through the (default) `futures-01` feature
+//! - Streaming object handles implementing tokio's `AsyncRead`, `AsyncWrite` and `AsyncSeek`,
+//!   with readahead and bounded in-flight writes, through the `tokio` feature
 //!
 //! ## Examples
 //!
@@ -69,6 +90,11 @@
 //!
 //! ### Asynchronous cluster I/O
 //!
+//! Every asynchronous operation returns a type implementing `std::future::Future`, and every
+//! stream implements the `futures-core` `Stream` trait, so they can be `.await`ed from any
+//! executor. With the `futures-01` feature, which is enabled by default, they also implement the
+//! `futures` 0.1 `Future` and `Stream` traits, as in the following example.
+//!
 //! ```rust,no_run
 //! # extern crate futures;
 //! # extern crate rad;
@@ -116,11 +142,15 @@ extern crate chrono;
 #[macro_use]
 extern crate error_chain;
 extern crate ffi_pool;
+#[cfg(feature = "futures-01")]
 extern crate futures;
+extern crate futures_core;
 #[macro_use]
 extern crate lazy_static;
 extern crate libc;
 extern crate stable_deref_trait;
+#[cfg(feature = "tokio")]
+extern crate tokio;
 
 pub use stable_deref_trait::StableDeref;
 
@@ -132,8 +162,10 @@ macro_rules! c {
     };
 }
 
+#[macro_use]
 mod async;
 mod errors;
+mod ffi;
 mod rados;
 
 pub use errors::*;
@@ -20,22 +20,54 @@
 //! # Ok(()) } fn main() {}
 //! ```
 
+use std::ffi::CStr;
 use std::ops::DerefMut;
 use std::mem;
 use std::path::Path;
 use std::ptr;
 use std::result::Result as StdResult;
 use std::sync::Arc;
+use std::task::Poll;
 
 use ceph::rados::{self, rados_completion_t, rados_ioctx_t, rados_t, Struct_rados_cluster_stat_t};
-use chrono::{DateTime, Local, TimeZone};
+use chrono::{DateTime, Local, TimeZone, Utc};
 use ffi_pool::CStringPool;
-use futures::prelude::*;
 use libc;
 use stable_deref_trait::StableDeref;
 
-use async::Completion;
+use async::{Completion, RadosFuture, Return, Wake};
 use errors::{self, Error, ErrorKind, Result};
+use ffi;
+
+#[cfg(feature = "tokio")]
+mod async_handle;
+mod command;
+mod copy;
+mod handle;
+mod list;
+mod lock;
+mod omap;
+mod ops;
+mod pool;
+mod snap;
+mod striper;
+mod watch;
+
+#[cfg(feature = "tokio")]
+pub use self::async_handle::*;
+pub use self::command::*;
+pub use self::handle::*;
+pub use self::list::*;
+pub use self::lock::*;
+pub use self::ops::*;
+pub use self::pool::*;
+pub use self::snap::*;
+pub use self::striper::*;
+pub use self::watch::*;
+
+/// The special namespace name which librados uses to denote "all namespaces", for listing
+/// operations. This is `LIBRADOS_ALL_NSPACES` in `librados.h`.
+const ALL_NAMESPACES: &str = "\u{1}";
 
 lazy_static! {
     /// A pool of `CString`s used for converting Rust strings which need to be passed into
@@ -43,6 +75,51 @@ lazy_static! {
     static ref POOL: CStringPool = CStringPool::new(128);
 }
 
+/// Read a string from a librados function which writes into a caller-supplied buffer, growing the
+/// buffer and trying again for as long as the function fails with `ERANGE`. The closure is passed
+/// a pointer to the buffer and its length.
+fn get_string<F>(mut f: F) -> Result<String>
+where
+    F: FnMut(*mut libc::c_char, usize) -> i32,
+{
+    let mut buf = vec![0u8; 256];
+
+    loop {
+        let err = f(buf.as_mut_ptr() as *mut libc::c_char, buf.len());
+
+        if err == -libc::ERANGE {
+            let len = buf.len();
+            buf = vec![0u8; len * 2];
+        } else {
+            errors::librados(err)?;
+            break;
+        }
+    }
+
+    let nul = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    buf.truncate(nul);
+
+    Ok(String::from_utf8(buf).map_err(|e| e.utf8_error())?)
+}
+
+/// Read a configuration option of a cluster handle, using `rados_conf_get`.
+fn conf_get(handle: rados_t, option: &str) -> Result<String> {
+    let option_cstr = POOL.get_str(option)?;
+
+    // Unlike most librados functions, `rados_conf_get` reports a short buffer with
+    // `ENAMETOOLONG`.
+    let value = get_string(|buf, len| {
+        match unsafe { rados::rados_conf_get(handle, option_cstr.as_ptr(), buf, len) } {
+            err if err == -libc::ENAMETOOLONG => -libc::ERANGE,
+            err => err,
+        }
+    })?;
+
+    mem::drop(option_cstr);
+
+    Ok(value)
+}
+
 /// A wrapper around a `rados_t` providing methods for configuring the connection before finalizing
 /// it.
 #[derive(Debug)]
@@ -94,6 +171,11 @@ impl ConnectionBuilder {
         Ok(self)
     }
 
+    /// Get the current value of a configuration option, using `rados_conf_get`.
+    pub fn conf_get(&self, option: &str) -> Result<String> {
+        conf_get(self.handle, option)
+    }
+
     /// Finish building the connection configuration and connect to the cluster.
     pub fn connect(self) -> Result<Connection> {
         errors::librados(unsafe { rados::rados_connect(self.handle) })?;
@@ -180,6 +262,48 @@ impl Connection {
         })
     }
 
+    /// Get the ID of the cluster, using `rados_cluster_fsid`.
+    pub fn fsid(&mut self) -> Result<String> {
+        let handle = self.conn.handle;
+
+        get_string(|buf, len| unsafe { rados::rados_cluster_fsid(handle, buf, len) })
+    }
+
+    /// Get the global ID of this client's connection to the cluster, using
+    /// `rados_get_instance_id`. This is the number in client names such as `client.4123`.
+    pub fn instance_id(&mut self) -> u64 {
+        unsafe { rados::rados_get_instance_id(self.conn.handle) }
+    }
+
+    /// Get the addresses of this client, as seen by the cluster, using `rados_getaddrs`.
+    pub fn get_addrs(&mut self) -> Result<String> {
+        let mut addrs = ptr::null_mut();
+
+        errors::librados(unsafe { ffi::rados_getaddrs(self.conn.handle, &mut addrs) })?;
+
+        let addrs_res = unsafe { CStr::from_ptr(addrs) }
+            .to_str()
+            .map(str::to_owned);
+
+        unsafe {
+            rados::rados_buffer_free(addrs);
+        }
+
+        Ok(addrs_res?)
+    }
+
+    /// Block until this client has the latest OSD map from the monitors, using
+    /// `rados_wait_for_latest_osdmap`. This is useful after creating or deleting a pool, for
+    /// example, so that the change is visible to further operations.
+    pub fn wait_for_latest_osdmap(&mut self) -> Result<()> {
+        errors::librados(unsafe { rados::rados_wait_for_latest_osdmap(self.conn.handle) })
+    }
+
+    /// Get the current value of a configuration option, using `rados_conf_get`.
+    pub fn conf_get(&mut self, option: &str) -> Result<String> {
+        conf_get(self.conn.handle, option)
+    }
+
     /// Fetch the `rados_ioctx_t` for the relevant pool, using `rados_ioctx_create`.
     pub fn get_pool_context(&mut self, pool_name: &str) -> Result<Context> {
         let pool_name_cstr = POOL.get_str(pool_name)?;
@@ -190,8 +314,11 @@ impl Connection {
         })?;
 
         Ok(Context {
-            _conn: self.conn.clone(),
+            conn: self.conn.clone(),
             handle: ioctx_handle,
+            locator_key: None,
+            read_snap: None,
+            write_snapc: None,
         })
     }
 
@@ -208,8 +335,11 @@ impl Connection {
         })?;
 
         Ok(Context {
-            _conn: self.conn.clone(),
+            conn: self.conn.clone(),
             handle: ioctx_handle,
+            locator_key: None,
+            read_snap: None,
+            write_snapc: None,
         })
     }
 }
@@ -218,31 +348,55 @@ impl Connection {
 #[derive(Debug)]
 pub struct UnitFuture {
     completion_res: StdResult<Completion<()>, Option<Error>>,
+    map_err: fn(Error) -> Error,
 }
 
 impl UnitFuture {
     fn new<F>(init: F) -> UnitFuture
+    where
+        F: FnOnce(rados_completion_t) -> Result<()>,
+    {
+        UnitFuture::with_map_err(|error| error, init)
+    }
+
+    /// Construct a `UnitFuture` whose error, if the operation fails, is converted with `map_err`.
+    fn with_map_err<F>(map_err: fn(Error) -> Error, init: F) -> UnitFuture
     where
         F: FnOnce(rados_completion_t) -> Result<()>,
     {
         UnitFuture {
             completion_res: Completion::new((), init).map_err(Some),
+            map_err,
+        }
+    }
+
+    /// Turn this future into one which yields the version of the object after the operation,
+    /// using `rados_aio_get_version`.
+    pub fn versioned(self) -> VersionFuture<()> {
+        VersionFuture {
+            completion_res: self.completion_res,
+            map_err: self.map_err,
         }
     }
 }
 
-impl Future for UnitFuture {
+impl RadosFuture for UnitFuture {
     type Item = ();
-    type Error = Error;
 
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+    fn poll_rados(&mut self, wake: &Wake) -> Poll<Result<Self::Item>> {
+        let map_err = self.map_err;
+
         match self.completion_res.as_mut() {
-            Ok(completion) => completion.poll().map(|async| async.map(|_| ())),
-            Err(error) => Err(error.take().unwrap()),
+            Ok(completion) => completion
+                .poll_rados(wake)
+                .map(|res| res.map(|_| ()).map_err(map_err)),
+            Err(error) => Poll::Ready(Err(error.take().unwrap())),
         }
     }
 }
 
+rados_future!([] UnitFuture);
+
 #[derive(Debug)]
 pub struct DataFuture<T> {
     completion_res: StdResult<Completion<T>, Option<Error>>,
@@ -257,20 +411,56 @@ impl<T> DataFuture<T> {
             completion_res: Completion::new(data, init).map_err(Some),
         }
     }
+
+    /// Construct a `DataFuture` which immediately fails with the given error, for when an
+    /// operation fails before it can be submitted to librados.
+    fn failed(error: Error) -> DataFuture<T> {
+        DataFuture {
+            completion_res: Err(Some(error)),
+        }
+    }
+
+    /// Poll for the full `Return` of the completion, including the version of the object.
+    fn poll_return(&mut self, wake: &Wake) -> Poll<Result<Return<T>>> {
+        match self.completion_res.as_mut() {
+            Ok(completion) => completion.poll_rados(wake),
+            Err(error) => Poll::Ready(Err(error.take().unwrap())),
+        }
+    }
 }
 
-impl<T> Future for DataFuture<T> {
+impl<T> RadosFuture for DataFuture<T> {
     type Item = T;
-    type Error = Error;
 
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+    fn poll_rados(&mut self, wake: &Wake) -> Poll<Result<Self::Item>> {
+        self.poll_return(wake).map(|res| res.map(|ret| ret.data))
+    }
+}
+
+rados_future!([T] DataFuture<T>);
+
+/// A future yielding the version of an object after an asynchronous operation on it completes.
+#[derive(Debug)]
+pub struct VersionFuture<T> {
+    completion_res: StdResult<Completion<T>, Option<Error>>,
+    map_err: fn(Error) -> Error,
+}
+
+impl<T> RadosFuture for VersionFuture<T> {
+    type Item = u64;
+
+    fn poll_rados(&mut self, wake: &Wake) -> Poll<Result<Self::Item>> {
         match self.completion_res.as_mut() {
-            Ok(completion) => completion.poll().map(|async| async.map(|ret| ret.data)),
-            Err(error) => Err(error.take().unwrap()),
+            Ok(completion) => completion
+                .poll_rados(wake)
+                .map(|res| res.map(|ret| ret.version).map_err(self.map_err)),
+            Err(error) => Poll::Ready(Err(error.take().unwrap())),
         }
     }
 }
 
+rados_future!([T] VersionFuture<T>);
+
 #[derive(Debug)]
 pub struct ReadFuture<B>
 where
@@ -293,80 +483,128 @@ where
     }
 }
 
-impl<B> Future for ReadFuture<B>
+impl<B> RadosFuture for ReadFuture<B>
 where
     B: StableDeref + DerefMut<Target = [u8]>,
 {
     type Item = (u32, B);
-    type Error = Error;
 
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+    fn poll_rados(&mut self, wake: &Wake) -> Poll<Result<Self::Item>> {
         match self.completion_res.as_mut() {
             Ok(completion) => completion
-                .poll()
-                .map(|async| async.map(|ret| (ret.value, ret.data))),
-            Err(error) => Err(error.take().unwrap()),
+                .poll_rados(wake)
+                .map(|res| res.map(|ret| (ret.value, ret.data))),
+            Err(error) => Poll::Ready(Err(error.take().unwrap())),
         }
     }
 }
 
+rados_future!([B] ReadFuture<B> where B: StableDeref + DerefMut<Target = [u8]>);
+
 #[derive(Debug)]
 pub struct StatFuture {
     data_future: DataFuture<Box<(u64, libc::time_t)>>,
 }
 
-impl Future for StatFuture {
+impl RadosFuture for StatFuture {
     type Item = Stat;
-    type Error = Error;
 
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        self.data_future.poll().map(|async| {
-            async.map(|boxed| {
-                let (size, last_modified) = *boxed;
+    fn poll_rados(&mut self, wake: &Wake) -> Poll<Result<Self::Item>> {
+        let ret = try_ready!(self.data_future.poll_return(wake));
+        let (size, last_modified) = *ret.data;
 
-                Stat {
-                    size,
-                    last_modified: Local.timestamp(last_modified, 0),
-                }
-            })
-        })
+        Poll::Ready(Ok(Stat {
+            size,
+            last_modified: Local.timestamp(last_modified, 0),
+            version: ret.version,
+        }))
     }
 }
 
+rados_future!([] StatFuture);
+
+#[derive(Debug)]
+pub struct Stat2Future {
+    data_future: DataFuture<Box<(u64, ffi::timespec)>>,
+}
+
+impl RadosFuture for Stat2Future {
+    type Item = Stat2;
+
+    fn poll_rados(&mut self, wake: &Wake) -> Poll<Result<Self::Item>> {
+        let ret = try_ready!(self.data_future.poll_return(wake));
+        let (size, last_modified) = *ret.data;
+
+        Poll::Ready(Ok(Stat2 {
+            size,
+            last_modified: utc_timestamp(last_modified),
+            version: ret.version,
+        }))
+    }
+}
+
+rados_future!([] Stat2Future);
+
 #[derive(Debug)]
 pub struct ExistsFuture {
     unit_future: UnitFuture,
 }
 
-impl Future for ExistsFuture {
+impl RadosFuture for ExistsFuture {
     type Item = bool;
-    type Error = Error;
 
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        match self.unit_future.poll() {
-            Ok(Async::Ready(())) => Ok(Async::Ready(true)),
-            Ok(Async::NotReady) => Ok(Async::NotReady),
+    fn poll_rados(&mut self, wake: &Wake) -> Poll<Result<Self::Item>> {
+        self.unit_future.poll_rados(wake).map(|res| match res {
+            Ok(()) => Ok(true),
             Err(Error(ErrorKind::Rados(err_code), _)) if err_code == libc::ENOENT as u32 => {
-                Ok(Async::Ready(false))
+                Ok(false)
             }
             Err(err) => Err(err),
-        }
+        })
     }
 }
 
+rados_future!([] ExistsFuture);
+
 /// Statistics for a single RADOS object.
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Stat {
     pub size: u64,
     pub last_modified: DateTime<Local>,
+
+    /// The version of the object, which is incremented by every write to it.
+    pub version: u64,
+}
+
+/// Statistics for a single RADOS object, with the modification time to the nanosecond, as
+/// returned by `Context::stat2`.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Stat2 {
+    pub size: u64,
+    pub last_modified: DateTime<Utc>,
+
+    /// The version of the object, which is incremented by every write to it.
+    pub version: u64,
+}
+
+/// Convert a `timespec` reported by librados into a UTC timestamp.
+fn utc_timestamp(time: ffi::timespec) -> DateTime<Utc> {
+    Utc.timestamp(time.tv_sec as i64, time.tv_nsec as u32)
 }
 
 /// A wrapper around a `rados_ioctx_t`, which also counts as a reference to the underlying
 /// `Connection`.
 #[derive(Debug)]
 pub struct Context {
-    _conn: Arc<ClusterHandle>,
+    conn: Arc<ClusterHandle>,
     handle: rados_ioctx_t,
+
+    // librados has no way to query the locator key, read snapshot or write snapshot context of
+    // an I/O context, so we keep track of them ourselves in order to carry them over to
+    // duplicated contexts.
+    locator_key: Option<String>,
+    read_snap: Option<u64>,
+    write_snapc: Option<SnapContext>,
 }
 
 // `Context` is safe to `Send`, but not `Sync`; this is because nothing about the
@@ -388,6 +626,158 @@ impl Drop for Context {
 }
 
 impl Context {
+    /// Open a second I/O context on the same pool, namespace, locator key and snapshots as this
+    /// one, using `rados_ioctx_create2`. This is used to hand work off to background threads
+    /// without sharing this context's handle.
+    fn duplicate(&mut self) -> Result<Context> {
+        let mut ioctx_handle = ptr::null_mut();
+
+        errors::librados(unsafe {
+            rados::rados_ioctx_create2(
+                self.conn.handle,
+                rados::rados_ioctx_get_id(self.handle),
+                &mut ioctx_handle,
+            )
+        })?;
+
+        let mut ctx = Context {
+            conn: self.conn.clone(),
+            handle: ioctx_handle,
+            locator_key: None,
+            read_snap: None,
+            write_snapc: None,
+        };
+
+        ctx.set_namespace(&self.namespace()?)?;
+        ctx.set_locator_key(self.locator_key.as_ref().map(String::as_str))?;
+        ctx.set_read_snapshot(self.read_snap);
+
+        if let Some(ref snapc) = self.write_snapc {
+            ctx.set_write_snap_context(snapc)?;
+        }
+
+        Ok(ctx)
+    }
+
+    /// Set the namespace which all further operations on this context will use, using
+    /// `rados_ioctx_set_namespace`. The default namespace is the empty string.
+    pub fn set_namespace(&mut self, namespace: &str) -> Result<()> {
+        let namespace_cstr = POOL.get_str(namespace)?;
+
+        unsafe {
+            rados::rados_ioctx_set_namespace(self.handle, namespace_cstr.as_ptr());
+        }
+
+        mem::drop(namespace_cstr);
+
+        Ok(())
+    }
+
+    /// Put this context into "all namespaces" mode, in which listing operations yield objects
+    /// from every namespace in the pool. Other operations on objects fail with `EINVAL` in this
+    /// mode.
+    pub fn set_all_namespaces(&mut self) -> Result<()> {
+        self.set_namespace(ALL_NAMESPACES)
+    }
+
+    /// Get the namespace this context is currently operating in, using
+    /// `rados_ioctx_get_namespace`.
+    ///
+    /// If the context is in "all namespaces" mode, this returns `"\u{1}"`, the value of
+    /// librados's `LIBRADOS_ALL_NSPACES`.
+    pub fn namespace(&mut self) -> Result<String> {
+        get_string(|buf, len| unsafe { ffi::rados_ioctx_get_namespace(self.handle, buf, len) })
+    }
+
+    /// Set the locator key which all further operations on this context will use, using
+    /// `rados_ioctx_locator_set_key`. Objects with the same locator key are placed in the same
+    /// placement group, regardless of their names. Passing `None` returns to using each object's
+    /// name to place it.
+    ///
+    /// Note that an object written with a locator key can only be found again through a context
+    /// using that same locator key.
+    pub fn set_locator_key(&mut self, key: Option<&str>) -> Result<()> {
+        match key {
+            Some(key) => {
+                let key_cstr = POOL.get_str(key)?;
+
+                unsafe {
+                    rados::rados_ioctx_locator_set_key(self.handle, key_cstr.as_ptr());
+                }
+
+                mem::drop(key_cstr);
+            }
+            None => unsafe {
+                rados::rados_ioctx_locator_set_key(self.handle, ptr::null());
+            },
+        }
+
+        self.locator_key = key.map(str::to_owned);
+
+        Ok(())
+    }
+
+    /// Get the locator key this context is currently using, if any.
+    pub fn locator_key(&self) -> Option<&str> {
+        self.locator_key.as_ref().map(String::as_str)
+    }
+
+    /// Get the ID of the pool this context operates on, using `rados_ioctx_get_id`.
+    pub fn pool_id(&mut self) -> u64 {
+        unsafe { rados::rados_ioctx_get_id(self.handle) as u64 }
+    }
+
+    /// Get the name of the pool this context operates on, using `rados_ioctx_get_pool_name`.
+    pub fn pool_name(&mut self) -> Result<String> {
+        let handle = self.handle;
+
+        get_string(|buf, len| unsafe {
+            rados::rados_ioctx_get_pool_name(handle, buf, len as libc::c_uint)
+        })
+    }
+
+    /// Check whether the pool this context operates on requires writes to be aligned, as
+    /// erasure-coded pools do, using `rados_ioctx_pool_requires_alignment2`. Appends to such a
+    /// pool must be a multiple of `Context::pool_required_alignment` in size.
+    pub fn pool_requires_alignment(&mut self) -> Result<bool> {
+        let mut requires = 0;
+
+        errors::librados(unsafe {
+            ffi::rados_ioctx_pool_requires_alignment2(self.handle, &mut requires)
+        })?;
+
+        Ok(requires != 0)
+    }
+
+    /// Get the alignment required for writes to the pool this context operates on, using
+    /// `rados_ioctx_pool_required_alignment2`. This is meaningless unless
+    /// `Context::pool_requires_alignment` returns `true`.
+    pub fn pool_required_alignment(&mut self) -> Result<u64> {
+        let mut alignment = 0;
+
+        errors::librados(unsafe {
+            ffi::rados_ioctx_pool_required_alignment2(self.handle, &mut alignment)
+        })?;
+
+        Ok(alignment)
+    }
+
+    /// Open a new context on the same pool as this one, operating in the given namespace. Every
+    /// operation on the returned context (including listing) is scoped to that namespace, while
+    /// this context is left unchanged.
+    pub fn with_namespace(&mut self, namespace: &str) -> Result<Context> {
+        let mut ctx = self.duplicate()?;
+        ctx.set_namespace(namespace)?;
+        Ok(ctx)
+    }
+
+    /// Get the version of the object last operated on through this context, using
+    /// `rados_get_last_version`. Asynchronous operations report the version of their object
+    /// through `UnitFuture::versioned` and similar methods instead.
+    pub fn last_version(&mut self) -> u64 {
+        unsafe { rados::rados_get_last_version(self.handle) }
+    }
+
     /// Fetch an extended attribute on a given RADOS object using `rados_getxattr`.
     ///
     /// * `size` - the size in bytes of the extended attribute.
@@ -546,6 +936,7 @@ impl Context {
         Ok(Stat {
             size,
             last_modified: Local.timestamp(time, 0),
+            version: self.last_version(),
         })
     }
 
@@ -692,6 +1083,55 @@ impl Context {
         StatFuture { data_future }
     }
 
+    /// Get the statistics of a given RADOS object, with the modification time to the
+    /// nanosecond, using `rados_stat2`.
+    pub fn stat2(&mut self, obj: &str) -> Result<Stat2> {
+        let object_id = POOL.get_str(obj)?;
+
+        let mut size = 0;
+        let mut time = ffi::timespec::default();
+
+        errors::librados(unsafe {
+            ffi::rados_stat2(self.handle, object_id.as_ptr(), &mut size, &mut time)
+        })?;
+
+        mem::drop(object_id);
+
+        Ok(Stat2 {
+            size,
+            last_modified: utc_timestamp(time),
+            version: self.last_version(),
+        })
+    }
+
+    /// Asynchronously retrieve statistics of a specific object from the cluster, with the
+    /// modification time to the nanosecond, using `rados_aio_stat2`.
+    pub fn stat2_async(&mut self, obj: &str) -> Stat2Future {
+        let mut boxed = Box::new((0, ffi::timespec::default()));
+        let size_ptr = &mut boxed.0 as *mut u64;
+        let time_ptr = &mut boxed.1 as *mut ffi::timespec;
+
+        let data_future = DataFuture::new(boxed, |completion_handle| {
+            let object_id = POOL.get_str(obj)?;
+
+            errors::librados(unsafe {
+                ffi::rados_aio_stat2(
+                    self.handle,
+                    object_id.as_ptr(),
+                    completion_handle,
+                    size_ptr,
+                    time_ptr,
+                )
+            })?;
+
+            mem::drop(object_id);
+
+            Ok(())
+        });
+
+        Stat2Future { data_future }
+    }
+
     /// Check whether or not a RADOS object exists under a given name, using `rados_stat` and
     /// checking the error code for `ENOENT`.
     pub fn exists(&mut self, obj: &str) -> Result<bool> {
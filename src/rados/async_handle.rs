@@ -0,0 +1,414 @@
+//! The asynchronous counterpart of `ObjectHandle`, implementing tokio's `AsyncRead`,
+//! `AsyncWrite` and `AsyncSeek` on top of `Context::read_async` and `Context::write_async`.
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io::{self, SeekFrom};
+use std::pin::Pin;
+use std::task::{Context as TaskContext, Poll};
+
+use libc;
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf};
+
+use errors::{self, Error, ErrorKind, Result};
+
+use super::handle::{offset_by, DEFAULT_BUFFER_SIZE};
+use super::{Context, ReadFuture, StatFuture, UnitFuture};
+
+/// The default number of chunks an `AsyncObjectHandle` reads ahead of the data it has returned.
+pub const DEFAULT_READAHEAD: usize = 2;
+
+/// The default number of chunks an `AsyncObjectHandle` may be writing at once.
+pub const DEFAULT_WRITES_IN_FLIGHT: usize = 4;
+
+/// A seek started by `AsyncSeek::start_seek` and not yet completed.
+#[derive(Debug)]
+enum PendingSeek {
+    /// A seek to a position already known.
+    To(u64),
+
+    /// A seek relative to the end of the object, waiting for buffered writes to complete and
+    /// then for the object's size.
+    End(i64, Option<StatFuture>),
+}
+
+/// A handle to a RADOS object which implements tokio's `AsyncRead`, `AsyncWrite` and
+/// `AsyncSeek`, created by `Context::open_async`. This requires the `tokio` feature.
+///
+/// Data is read and written in chunks of a fixed size. Reading keeps up to `readahead` chunks
+/// following the current position in flight at once, so that the object can be streamed without
+/// waiting on a round trip for every chunk. Writes are gathered into chunks, of which at most
+/// `writes_in_flight` are written at once; once that many are in flight, further writes wait for
+/// the oldest to complete, which bounds the memory used by a long stream of writes.
+///
+/// Buffered and in-flight writes are completed before any read and before seeking relative to the
+/// end of the object, and on `poll_flush` or `poll_shutdown`. Unlike `ObjectHandle`, dropping an
+/// `AsyncObjectHandle` does *not* send buffered writes, and leaves any in-flight writes to
+/// complete or fail unobserved; shut the handle down to be sure every write has landed.
+#[derive(Debug)]
+pub struct AsyncObjectHandle {
+    context: Context,
+    object: String,
+    position: u64,
+    chunk_size: usize,
+    readahead: usize,
+    writes_in_flight: usize,
+
+    // Data read from the object; `read_buf[read_pos..]` starts at `position`.
+    read_buf: Vec<u8>,
+    read_pos: usize,
+
+    // Reads in flight, in order of offset. The first starts where the read buffer ends, and each
+    // following one `chunk_size` bytes after the last; `next_read` is where the next would start.
+    reads: VecDeque<ReadFuture<Vec<u8>>>,
+    next_read: u64,
+    read_eof: bool,
+
+    // Data written but not yet sent to the object, to be written at `write_offset`.
+    write_buf: Vec<u8>,
+    write_offset: u64,
+    writes: VecDeque<UnitFuture>,
+
+    seek: Option<PendingSeek>,
+}
+
+impl AsyncObjectHandle {
+    /// The name of the object this handle refers to.
+    pub fn object(&self) -> &str {
+        &self.object
+    }
+
+    /// The current position of the handle within the object.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// The size of the chunks this handle reads and writes.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Set the size of the chunks this handle reads and writes. Chunks already being read or
+    /// written keep their old size.
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        self.chunk_size = cmp::max(chunk_size, 1);
+    }
+
+    /// The number of chunks this handle reads ahead of the data it has returned.
+    pub fn readahead(&self) -> usize {
+        self.readahead
+    }
+
+    /// Set the number of chunks this handle reads ahead of the data it has returned. At least
+    /// one chunk is always read at a time.
+    pub fn set_readahead(&mut self, readahead: usize) {
+        self.readahead = cmp::max(readahead, 1);
+    }
+
+    /// The number of chunks this handle may be writing at once.
+    pub fn writes_in_flight(&self) -> usize {
+        self.writes_in_flight
+    }
+
+    /// Set the number of chunks this handle may be writing at once, which must be at least one.
+    pub fn set_writes_in_flight(&mut self, writes_in_flight: usize) {
+        self.writes_in_flight = cmp::max(writes_in_flight, 1);
+    }
+
+    /// Throw away any data read ahead, so that reading starts again from the current position.
+    fn reset_reads(&mut self) {
+        self.read_buf.clear();
+        self.read_pos = 0;
+        self.reads.clear();
+        self.next_read = self.position;
+        self.read_eof = false;
+    }
+
+    /// Start reading chunks ahead, until `readahead` chunks are in flight or the end of the
+    /// object has been seen.
+    fn start_reads(&mut self) {
+        while !self.read_eof && self.reads.len() < self.readahead {
+            let buf = vec![0; self.chunk_size];
+            let read = self.context.read_async(&self.object, buf, self.next_read);
+
+            self.reads.push_back(read);
+            self.next_read += self.chunk_size as u64;
+        }
+    }
+
+    /// Start writing the write buffer. librados copies the data as the write is started, so the
+    /// buffer can be reused immediately.
+    fn start_write(&mut self) {
+        let write = self.context
+            .write_async(&self.object, &self.write_buf, self.write_offset);
+
+        self.writes.push_back(write);
+        self.write_buf.clear();
+    }
+
+    /// Wait until at most `limit` writes are in flight.
+    fn poll_writes(&mut self, cx: &mut TaskContext, limit: usize) -> Poll<Result<()>> {
+        while self.writes.len() > limit {
+            // A completed write must be dropped whether or not it failed, as it cannot be polled
+            // again.
+            let res = match Pin::new(self.writes.front_mut().unwrap()).poll(cx) {
+                Poll::Ready(res) => res,
+                Poll::Pending => return Poll::Pending,
+            };
+
+            self.writes.pop_front();
+            res?;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Start writing the write buffer if it holds anything, waiting for a write to complete
+    /// first if too many are in flight.
+    fn poll_start_write(&mut self, cx: &mut TaskContext) -> Poll<Result<()>> {
+        if !self.write_buf.is_empty() {
+            let limit = self.writes_in_flight - 1;
+            try_ready!(self.poll_writes(cx, limit));
+
+            self.start_write();
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Send any buffered writes, and wait for every write in flight to complete.
+    fn poll_flush_writes(&mut self, cx: &mut TaskContext) -> Poll<Result<()>> {
+        try_ready!(self.poll_start_write(cx));
+
+        self.poll_writes(cx, 0)
+    }
+
+    /// Copy as much data from the read buffer as fits into `buf`.
+    fn copy_read(&mut self, buf: &mut ReadBuf) {
+        let buffered = &self.read_buf[self.read_pos..];
+        let read = cmp::min(buffered.len(), buf.remaining());
+
+        buf.put_slice(&buffered[..read]);
+        self.read_pos += read;
+        self.position += read as u64;
+    }
+}
+
+impl AsyncRead for AsyncObjectHandle {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext,
+        buf: &mut ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        // Reads must see writes made through this handle.
+        if !this.write_buf.is_empty() || !this.writes.is_empty() {
+            try_ready!(this.poll_flush_writes(cx).map_err(errors::io_error));
+            this.reset_reads();
+        }
+
+        while buf.remaining() > 0 {
+            if this.read_pos < this.read_buf.len() {
+                this.copy_read(buf);
+                break;
+            }
+
+            if this.read_eof && this.reads.is_empty() {
+                break;
+            }
+
+            this.start_reads();
+
+            match Pin::new(this.reads.front_mut().unwrap()).poll(cx) {
+                Poll::Ready(Ok((read, mut data))) => {
+                    this.reads.pop_front();
+
+                    // A short read means we have reached the end of the object, and any reads
+                    // further ahead can only come back empty.
+                    if (read as usize) < data.len() {
+                        this.read_eof = true;
+                        this.reads.clear();
+                    }
+
+                    data.truncate(read as usize);
+                    this.read_buf = data;
+                    this.read_pos = 0;
+                }
+                Poll::Ready(Err(error)) => {
+                    this.reset_reads();
+
+                    return Poll::Ready(Err(errors::io_error(error)));
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for AsyncObjectHandle {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        let contiguous = this.write_offset + this.write_buf.len() as u64 == this.position;
+
+        if !contiguous || this.write_buf.len() >= this.chunk_size {
+            try_ready!(this.poll_start_write(cx).map_err(errors::io_error));
+        }
+
+        // Anything we have read ahead may be about to be overwritten.
+        this.reset_reads();
+
+        if this.write_buf.is_empty() {
+            this.write_offset = this.position;
+        }
+
+        let written = cmp::min(buf.len(), this.chunk_size - this.write_buf.len());
+        this.write_buf.extend_from_slice(&buf[..written]);
+        this.position += written as u64;
+
+        // Start writing a full chunk right away if we can, rather than on the next write.
+        if this.write_buf.len() >= this.chunk_size && this.writes.len() < this.writes_in_flight {
+            this.start_write();
+        }
+
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut TaskContext) -> Poll<io::Result<()>> {
+        self.get_mut()
+            .poll_flush_writes(cx)
+            .map_err(errors::io_error)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut TaskContext) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+impl AsyncSeek for AsyncObjectHandle {
+    fn start_seek(self: Pin<&mut Self>, pos: SeekFrom) -> io::Result<()> {
+        let this = self.get_mut();
+
+        if this.seek.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "cannot start a seek while another is in progress",
+            ));
+        }
+
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => offset_by(this.position, delta),
+            SeekFrom::End(delta) => {
+                this.seek = Some(PendingSeek::End(delta, None));
+
+                return Ok(());
+            }
+        };
+
+        match position {
+            Some(position) => {
+                this.seek = Some(PendingSeek::To(position));
+
+                Ok(())
+            }
+            None => Err(invalid_seek()),
+        }
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut TaskContext) -> Poll<io::Result<u64>> {
+        let this = self.get_mut();
+
+        let position = match this.seek.take() {
+            None => return Poll::Ready(Ok(this.position)),
+            Some(PendingSeek::To(position)) => position,
+            Some(PendingSeek::End(delta, stat)) => {
+                let mut stat = match stat {
+                    Some(stat) => stat,
+                    None => match this.poll_flush_writes(cx) {
+                        Poll::Ready(Ok(())) => this.context.stat_async(&this.object),
+                        Poll::Ready(Err(error)) => {
+                            return Poll::Ready(Err(errors::io_error(error)))
+                        }
+                        Poll::Pending => {
+                            this.seek = Some(PendingSeek::End(delta, None));
+
+                            return Poll::Pending;
+                        }
+                    },
+                };
+
+                // An object which does not exist yet has size zero.
+                let size = match Pin::new(&mut stat).poll(cx) {
+                    Poll::Ready(Ok(stat)) => stat.size,
+                    Poll::Ready(Err(Error(ErrorKind::Rados(err), _)))
+                        if err == libc::ENOENT as u32 =>
+                    {
+                        0
+                    }
+                    Poll::Ready(Err(error)) => return Poll::Ready(Err(errors::io_error(error))),
+                    Poll::Pending => {
+                        this.seek = Some(PendingSeek::End(delta, Some(stat)));
+
+                        return Poll::Pending;
+                    }
+                };
+
+                match offset_by(size, delta) {
+                    Some(position) => position,
+                    None => return Poll::Ready(Err(invalid_seek())),
+                }
+            }
+        };
+
+        this.position = position;
+        this.reset_reads();
+
+        Poll::Ready(Ok(position))
+    }
+}
+
+fn invalid_seek() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "invalid seek to a negative or overflowing position",
+    )
+}
+
+impl Context {
+    /// Open a handle to the given object, which need not exist yet, for reading and writing it
+    /// through tokio's `AsyncRead`, `AsyncWrite` and `AsyncSeek` traits. The handle starts at the
+    /// beginning of the object, reading and writing chunks of `DEFAULT_BUFFER_SIZE` bytes, with
+    /// `DEFAULT_READAHEAD` and `DEFAULT_WRITES_IN_FLIGHT` chunks in flight. This requires the
+    /// `tokio` feature.
+    ///
+    /// As with `Context::open`, the handle uses its own I/O context, with the same pool, namespace,
+    /// locator key, read snapshot and write snapshot context as this one.
+    pub fn open_async(&mut self, obj: &str) -> Result<AsyncObjectHandle> {
+        Ok(AsyncObjectHandle {
+            context: self.duplicate()?,
+            object: obj.to_owned(),
+            position: 0,
+            chunk_size: DEFAULT_BUFFER_SIZE,
+            readahead: DEFAULT_READAHEAD,
+            writes_in_flight: DEFAULT_WRITES_IN_FLIGHT,
+            read_buf: Vec::new(),
+            read_pos: 0,
+            reads: VecDeque::new(),
+            next_read: 0,
+            read_eof: false,
+            write_buf: Vec::new(),
+            write_offset: 0,
+            writes: VecDeque::new(),
+            seek: None,
+        })
+    }
+}
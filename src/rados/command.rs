@@ -0,0 +1,410 @@
+//! Sending administrative commands to the cluster, as the `ceph` CLI does. Commands are JSON
+//! objects such as `{"prefix": "osd tree", "format": "json"}`, sent to the monitors, to a
+//! specific OSD, or to the primary OSD of a specific placement group; the output of a command is
+//! some data, usually JSON when a JSON format is requested, and a human-readable status string.
+//!
+//! librados provides no asynchronous versions of these functions, so the `_async` variants run
+//! the command on a background thread.
+
+use std::convert::TryFrom;
+use std::ffi::CString;
+use std::fmt;
+use std::ptr;
+use std::result::Result as StdResult;
+use std::slice;
+use std::str::{self, FromStr};
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use std::thread;
+
+use ceph::rados;
+use libc;
+
+use async::{RadosFuture, Wake, WakerSlot};
+use errors::{Error, ErrorKind, Result};
+
+use super::{ClusterHandle, Connection};
+
+/// The output of a successful command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommandOutput {
+    /// The output data of the command, such as JSON when it was asked for with `"format":
+    /// "json"`.
+    pub data: Vec<u8>,
+
+    /// The human-readable status string of the command, which is often empty.
+    pub status: String,
+}
+
+impl CommandOutput {
+    /// Get the output data of the command as a string.
+    pub fn data_str(&self) -> Result<&str> {
+        Ok(str::from_utf8(&self.data)?)
+    }
+}
+
+/// The ID of a placement group: the ID of its pool, followed by its placement seed. Placement
+/// group IDs are written as the pool ID in decimal and the seed in hexadecimal, separated by a
+/// dot, such as `1.2f`.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PgId {
+    pub pool: u64,
+    pub seed: u32,
+}
+
+impl FromStr for PgId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<PgId> {
+        let mut parts = s.splitn(2, '.');
+
+        match (parts.next(), parts.next()) {
+            (Some(pool), Some(seed)) if !pool.is_empty() && !seed.is_empty() => {
+                match (pool.parse(), u32::from_str_radix(seed, 16)) {
+                    (Ok(pool), Ok(seed)) => Ok(PgId { pool, seed }),
+                    _ => bail!("invalid placement group ID `{}`", s),
+                }
+            }
+            _ => bail!("invalid placement group ID `{}`", s),
+        }
+    }
+}
+
+impl fmt::Display for PgId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{:x}", self.pool, self.seed)
+    }
+}
+
+/// The output buffers allocated by librados for a command, freed with `rados_buffer_free`.
+struct CommandBuffers {
+    outbuf: *mut libc::c_char,
+    outbuf_len: libc::size_t,
+    outs: *mut libc::c_char,
+    outs_len: libc::size_t,
+}
+
+impl CommandBuffers {
+    fn new() -> CommandBuffers {
+        CommandBuffers {
+            outbuf: ptr::null_mut(),
+            outbuf_len: 0,
+            outs: ptr::null_mut(),
+            outs_len: 0,
+        }
+    }
+
+    fn copy(buf: *mut libc::c_char, len: libc::size_t) -> Vec<u8> {
+        if buf.is_null() {
+            Vec::new()
+        } else {
+            unsafe { slice::from_raw_parts(buf as *const u8, len).to_vec() }
+        }
+    }
+
+    /// Collect the output of a command, given its return code. A failed command is reported as
+    /// `ErrorKind::CommandFailed`, carrying the status string, which usually explains the
+    /// failure.
+    fn finish(self, err: i32) -> Result<CommandOutput> {
+        let status = String::from_utf8_lossy(&Self::copy(self.outs, self.outs_len)).into_owned();
+
+        if err < 0 {
+            bail!(ErrorKind::CommandFailed(-err as u32, status));
+        }
+
+        Ok(CommandOutput {
+            data: Self::copy(self.outbuf, self.outbuf_len),
+            status,
+        })
+    }
+}
+
+impl Drop for CommandBuffers {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.outbuf.is_null() {
+                rados::rados_buffer_free(self.outbuf);
+            }
+
+            if !self.outs.is_null() {
+                rados::rados_buffer_free(self.outs);
+            }
+        }
+    }
+}
+
+/// Where to send a command.
+#[derive(Debug)]
+enum Target {
+    /// Whichever monitor librados is connected to.
+    Mon,
+
+    /// The monitor with the given name.
+    MonNamed(CString),
+
+    /// The OSD with the given ID.
+    Osd(libc::c_int),
+
+    /// The primary OSD of the placement group with the given ID, formatted as a string.
+    Pg(CString),
+}
+
+impl Target {
+    /// Target the OSD with the given ID, which librados takes as a C `int`.
+    fn osd(osd: u32) -> Result<Target> {
+        match libc::c_int::try_from(osd) {
+            Ok(osd) => Ok(Target::Osd(osd)),
+            Err(_) => bail!("invalid OSD ID {}", osd),
+        }
+    }
+}
+
+/// A command along with its input, owned so that it can be sent to a background thread.
+#[derive(Debug)]
+struct Command {
+    target: Target,
+    cmd: CString,
+    input: Vec<u8>,
+}
+
+impl Command {
+    fn new(target: Target, cmd: &str, input: &[u8]) -> Result<Command> {
+        Ok(Command {
+            target,
+            cmd: CString::new(cmd)?,
+            input: input.to_vec(),
+        })
+    }
+
+    fn run(&self, cluster: &ClusterHandle) -> Result<CommandOutput> {
+        let mut cmds = [self.cmd.as_ptr()];
+        let inbuf = self.input.as_ptr() as *const libc::c_char;
+        let mut bufs = CommandBuffers::new();
+
+        let err = unsafe {
+            match self.target {
+                Target::Mon => rados::rados_mon_command(
+                    cluster.handle,
+                    cmds.as_mut_ptr(),
+                    cmds.len(),
+                    inbuf,
+                    self.input.len(),
+                    &mut bufs.outbuf,
+                    &mut bufs.outbuf_len,
+                    &mut bufs.outs,
+                    &mut bufs.outs_len,
+                ),
+                Target::MonNamed(ref name) => rados::rados_mon_command_target(
+                    cluster.handle,
+                    name.as_ptr(),
+                    cmds.as_mut_ptr(),
+                    cmds.len(),
+                    inbuf,
+                    self.input.len(),
+                    &mut bufs.outbuf,
+                    &mut bufs.outbuf_len,
+                    &mut bufs.outs,
+                    &mut bufs.outs_len,
+                ),
+                Target::Osd(osd) => rados::rados_osd_command(
+                    cluster.handle,
+                    osd,
+                    cmds.as_mut_ptr(),
+                    cmds.len(),
+                    inbuf,
+                    self.input.len(),
+                    &mut bufs.outbuf,
+                    &mut bufs.outbuf_len,
+                    &mut bufs.outs,
+                    &mut bufs.outs_len,
+                ),
+                Target::Pg(ref pg) => rados::rados_pg_command(
+                    cluster.handle,
+                    pg.as_ptr(),
+                    cmds.as_mut_ptr(),
+                    cmds.len(),
+                    inbuf,
+                    self.input.len(),
+                    &mut bufs.outbuf,
+                    &mut bufs.outbuf_len,
+                    &mut bufs.outs,
+                    &mut bufs.outs_len,
+                ),
+            }
+        };
+
+        bufs.finish(err)
+    }
+}
+
+/// A cluster handle which may be sent to the thread running an asynchronous command. The
+/// `rados_t` itself is thread-safe; it is only kept from being `Send` to keep `Connection` from
+/// being `Sync`.
+struct SendCluster(Arc<ClusterHandle>);
+
+unsafe impl Send for SendCluster {}
+
+/// The state shared between a `CommandFuture` and the thread running its command.
+#[derive(Debug)]
+struct CommandShared {
+    output: Mutex<Option<Result<CommandOutput>>>,
+    waker: WakerSlot,
+}
+
+/// The type of a command running in the background.
+#[derive(Debug)]
+pub struct CommandFuture {
+    shared_res: StdResult<Arc<CommandShared>, Option<Error>>,
+}
+
+impl CommandFuture {
+    fn spawn(conn: &Arc<ClusterHandle>, command_res: Result<Command>) -> CommandFuture {
+        let command = match command_res {
+            Ok(command) => command,
+            Err(error) => {
+                return CommandFuture {
+                    shared_res: Err(Some(error)),
+                }
+            }
+        };
+
+        let cluster = SendCluster(conn.clone());
+        let shared = Arc::new(CommandShared {
+            output: Mutex::new(None),
+            waker: WakerSlot::new(),
+        });
+
+        {
+            let shared = shared.clone();
+
+            thread::spawn(move || {
+                let SendCluster(cluster) = cluster;
+                let output = command.run(&cluster);
+
+                *shared.output.lock().unwrap() = Some(output);
+                shared.waker.wake();
+            });
+        }
+
+        CommandFuture {
+            shared_res: Ok(shared),
+        }
+    }
+}
+
+impl RadosFuture for CommandFuture {
+    type Item = CommandOutput;
+
+    fn poll_rados(&mut self, wake: &Wake) -> Poll<Result<Self::Item>> {
+        match self.shared_res.as_mut() {
+            Ok(shared) => {
+                // The task must be registered before checking for the output, so that output
+                // produced in between cannot be missed.
+                shared.waker.register(wake);
+
+                match shared.output.lock().unwrap().take() {
+                    Some(output) => Poll::Ready(output),
+                    None => Poll::Pending,
+                }
+            }
+            Err(error) => Poll::Ready(Err(error.take().unwrap())),
+        }
+    }
+}
+
+rados_future!([] CommandFuture);
+
+impl Connection {
+    /// Send a JSON command to the monitors, using `rados_mon_command`. `input` is passed as the
+    /// command's input buffer and may be empty.
+    pub fn mon_command(&mut self, cmd: &str, input: &[u8]) -> Result<CommandOutput> {
+        Command::new(Target::Mon, cmd, input)?.run(&self.conn)
+    }
+
+    /// Send a JSON command to the monitor with the given name, using
+    /// `rados_mon_command_target`.
+    pub fn mon_command_target(
+        &mut self,
+        mon_name: &str,
+        cmd: &str,
+        input: &[u8],
+    ) -> Result<CommandOutput> {
+        let target = Target::MonNamed(CString::new(mon_name)?);
+
+        Command::new(target, cmd, input)?.run(&self.conn)
+    }
+
+    /// Asynchronously send a JSON command to the monitors, as with `Connection::mon_command`.
+    pub fn mon_command_async(&mut self, cmd: &str, input: &[u8]) -> CommandFuture {
+        CommandFuture::spawn(&self.conn, Command::new(Target::Mon, cmd, input))
+    }
+
+    /// Asynchronously send a JSON command to the monitor with the given name, as with
+    /// `Connection::mon_command_target`.
+    pub fn mon_command_target_async(
+        &mut self,
+        mon_name: &str,
+        cmd: &str,
+        input: &[u8],
+    ) -> CommandFuture {
+        let command_res = CString::new(mon_name)
+            .map_err(Error::from)
+            .and_then(|name| Command::new(Target::MonNamed(name), cmd, input));
+
+        CommandFuture::spawn(&self.conn, command_res)
+    }
+
+    /// Send a JSON command, such as `{"prefix": "version"}`, to the OSD with the given ID, using
+    /// `rados_osd_command`.
+    pub fn osd_command(&mut self, osd: u32, cmd: &str, input: &[u8]) -> Result<CommandOutput> {
+        Command::new(Target::osd(osd)?, cmd, input)?.run(&self.conn)
+    }
+
+    /// Send a JSON command, such as `{"prefix": "query"}` or `{"prefix": "list_unfound"}`, to
+    /// the primary OSD of the given placement group, using `rados_pg_command`.
+    pub fn pg_command(&mut self, pg: PgId, cmd: &str, input: &[u8]) -> Result<CommandOutput> {
+        let target = Target::Pg(CString::new(pg.to_string())?);
+
+        Command::new(target, cmd, input)?.run(&self.conn)
+    }
+
+    /// Asynchronously send a JSON command to the OSD with the given ID, as with
+    /// `Connection::osd_command`.
+    pub fn osd_command_async(&mut self, osd: u32, cmd: &str, input: &[u8]) -> CommandFuture {
+        let command_res = Target::osd(osd).and_then(|target| Command::new(target, cmd, input));
+
+        CommandFuture::spawn(&self.conn, command_res)
+    }
+
+    /// Asynchronously send a JSON command to the primary OSD of the given placement group, as
+    /// with `Connection::pg_command`.
+    pub fn pg_command_async(&mut self, pg: PgId, cmd: &str, input: &[u8]) -> CommandFuture {
+        let command_res = CString::new(pg.to_string())
+            .map_err(Error::from)
+            .and_then(|pg| Command::new(Target::Pg(pg), cmd, input));
+
+        CommandFuture::spawn(&self.conn, command_res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PgId;
+
+    #[test]
+    fn pg_id_round_trip() {
+        for &(s, pool, seed) in &[("0.0", 0, 0), ("1.2f", 1, 0x2f), ("12.ffffffff", 12, !0)] {
+            let pg = s.parse::<PgId>().unwrap();
+
+            assert_eq!(pg, PgId { pool, seed });
+            assert_eq!(pg.to_string(), s);
+        }
+    }
+
+    #[test]
+    fn pg_id_invalid() {
+        for s in &["", "1", "1.", ".2f", "x.1", "1.zz", "-1.2", "1.2.3", "1.100000000"] {
+            assert!(s.parse::<PgId>().is_err(), "`{}` parsed as a PgId", s);
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! Copying and renaming objects.
+//!
+//! The librados C API has no equivalent of the C++ API's `copy_from` write operation, which
+//! copies an object within the cluster, so objects are copied through the client instead. The
+//! copy is streamed a chunk of data and a page of omap entries at a time, so copying a large
+//! object only ever holds a bounded amount of it in memory.
+
+use std::collections::BTreeMap;
+
+use ceph::rados;
+use libc;
+
+use errors::{Error, ErrorKind, Result};
+
+use super::Context;
+use super::ops::{OmapPage, ReadOp, WriteOp};
+
+/// The amount of object data copied in each round trip.
+const COPY_CHUNK_SIZE: usize = 4 << 20;
+
+/// The number of omap entries copied in each round trip.
+const COPY_OMAP_PAGE: u64 = 1024;
+
+/// Everything but the bulk of an object's data and omap, as fetched by the first round trip of a
+/// copy.
+struct CopyHead {
+    version: u64,
+    size: u64,
+    data: Vec<u8>,
+    xattrs: BTreeMap<String, Vec<u8>>,
+    omap: OmapPage<(String, Vec<u8>)>,
+}
+
+impl CopyHead {
+    fn read(src: &mut Context, src_obj: &str) -> Result<CopyHead> {
+        let mut read_op = ReadOp::new();
+        let stat = read_op.stat();
+        let xattrs = read_op.xattrs();
+        let data = read_op.read(0, COPY_CHUNK_SIZE);
+        let omap = read_op.omap_vals("", "", COPY_OMAP_PAGE)?;
+
+        let mut completed = src.read_op(src_obj, read_op)?;
+
+        Ok(CopyHead {
+            version: completed.version(),
+            size: completed.take(stat)?.size,
+            data: completed.take(data)?,
+            xattrs: completed.take(xattrs)?,
+            omap: completed.take(omap)?,
+        })
+    }
+}
+
+impl Context {
+    /// Copy an object, along with its extended attributes and omap, from another context into
+    /// this one. The source context may be on a different pool or namespace. Any existing object
+    /// named `dst_obj` is replaced. Returns the version of the source object which was copied.
+    ///
+    /// The copy is made through the client, over as many round trips as it takes: every read of
+    /// the source is guarded on its version, so if the source is modified partway through, the
+    /// copy fails with `ERANGE` or `EOVERFLOW`. The first chunk of data and page of omap entries
+    /// replace the contents of `dst_obj` in a single atomic write, and the rest are written after
+    /// it, so an object too large to copy in one round trip may be left partially copied to
+    /// `dst_obj` if the copy fails. Omap headers cannot be read through the C API, and so are not
+    /// copied.
+    ///
+    /// Copying an object onto itself fails.
+    pub fn copy_from(&mut self, src: &mut Context, src_obj: &str, dst_obj: &str) -> Result<u64> {
+        if self.same_object(dst_obj, src, src_obj)? {
+            bail!("cannot copy object `{}` onto itself", src_obj);
+        }
+
+        let head = CopyHead::read(src, src_obj)?;
+        let version = head.version;
+
+        self.copy_into(src, src_obj, head, dst_obj)?;
+
+        Ok(version)
+    }
+
+    /// Whether `obj` in this context and `other_obj` in `other` are the same object.
+    fn same_object(&mut self, obj: &str, other: &mut Context, other_obj: &str) -> Result<bool> {
+        let same_pool = unsafe {
+            rados::rados_ioctx_get_id(self.handle) == rados::rados_ioctx_get_id(other.handle)
+        };
+
+        Ok(obj == other_obj && same_pool && self.locator_key == other.locator_key
+            && self.namespace()? == other.namespace()?)
+    }
+
+    /// Replace `dst_obj` with a copy of `src_obj`, whose first round trip has already been made.
+    fn copy_into(
+        &mut self,
+        src: &mut Context,
+        src_obj: &str,
+        head: CopyHead,
+        dst_obj: &str,
+    ) -> Result<()> {
+        let CopyHead {
+            version,
+            size,
+            data,
+            xattrs,
+            mut omap,
+        } = head;
+
+        // Only the names of the destination's existing attributes are needed, so that they can be
+        // removed, but librados cannot list them without their values.
+        let dst_xattrs = match self.read_output(dst_obj, |op| Ok(op.xattrs())) {
+            Err(Error(ErrorKind::Rados(err), _)) if err == libc::ENOENT as u32 => BTreeMap::new(),
+            res => res?,
+        };
+
+        // Reset the destination and write the first round trip's worth of it in one atomic
+        // operation, so that it is never seen with a mix of its old and new contents.
+        let mut write_op = WriteOp::new().create(false).omap_clear();
+
+        for key in dst_xattrs.keys() {
+            write_op = write_op.rm_xattr(key)?;
+        }
+
+        write_op = write_op.write_full(&data);
+
+        for (key, value) in xattrs {
+            write_op = write_op.set_xattr(&key, &value)?;
+        }
+
+        if !omap.entries.is_empty() {
+            write_op = write_op.omap_set(omap.entries.iter().map(|&(ref k, ref v)| (k, v)))?;
+        }
+
+        self.operate(dst_obj, write_op)?;
+
+        let mut offset = data.len() as u64;
+
+        while offset < size || omap.more {
+            let mut read_op = ReadOp::new();
+            read_op.assert_version(version);
+
+            let data = if offset < size {
+                Some(read_op.read(offset, COPY_CHUNK_SIZE))
+            } else {
+                None
+            };
+
+            let next_omap = if omap.more {
+                let start_after = match omap.entries.last() {
+                    Some(&(ref key, _)) => key.as_str(),
+                    None => "",
+                };
+
+                Some(read_op.omap_vals(start_after, "", COPY_OMAP_PAGE)?)
+            } else {
+                None
+            };
+
+            let mut completed = src.read_op(src_obj, read_op)?;
+            let mut write_op = WriteOp::new();
+
+            if let Some(data) = data {
+                let data = completed.take(data)?;
+
+                if data.is_empty() {
+                    bail!("object `{}` ended early while being copied", src_obj);
+                }
+
+                write_op = write_op.write(&data, offset);
+                offset += data.len() as u64;
+            }
+
+            if let Some(next_omap) = next_omap {
+                omap = completed.take(next_omap)?;
+
+                if !omap.entries.is_empty() {
+                    write_op =
+                        write_op.omap_set(omap.entries.iter().map(|&(ref k, ref v)| (k, v)))?;
+                }
+            }
+
+            self.operate(dst_obj, write_op)?;
+        }
+
+        Ok(())
+    }
+
+    /// Rename an object within this context's pool and namespace, by copying it with
+    /// `Context::copy_from` and then removing the original. The removal is guarded on the
+    /// original being unchanged since it was copied; if it has changed, this fails with `ERANGE`
+    /// or `EOVERFLOW`, leaving both objects in place.
+    pub fn rename(&mut self, src_obj: &str, dst_obj: &str) -> Result<()> {
+        if src_obj == dst_obj {
+            bail!("cannot rename object `{}` to itself", src_obj);
+        }
+
+        let mut src = self.duplicate()?;
+        let version = self.copy_from(&mut src, src_obj, dst_obj)?;
+
+        src.operate(src_obj, WriteOp::new().assert_version(version).remove())
+    }
+}
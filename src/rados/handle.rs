@@ -0,0 +1,235 @@
+//! File-like access to a single RADOS object, through the standard `Read`, `Write` and `Seek`
+//! traits.
+
+use std::cmp;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::mem;
+
+use libc;
+
+use errors::{self, Error, ErrorKind, Result};
+
+use super::Context;
+
+/// The default size of the read and write buffers of an `ObjectHandle`: 4 MiB, the default
+/// object size used by RBD and CephFS.
+pub const DEFAULT_BUFFER_SIZE: usize = 4 << 20;
+
+/// A handle to a RADOS object which implements `Read`, `Write` and `Seek`, created by
+/// `Context::open`.
+///
+/// Reads and writes are buffered, so that many small reads or writes become a few large
+/// `Context::read` and `Context::write` calls. Buffered writes are sent to the object when the
+/// buffer fills, before any read or seek to the end of the object, and on `flush` or `finish`.
+/// Dropping a handle also sends any buffered writes, but ignores any error in doing so; use
+/// `finish` to find out whether they succeeded.
+#[derive(Debug)]
+pub struct ObjectHandle {
+    context: Context,
+    object: String,
+    position: u64,
+    buffer_size: usize,
+
+    // Data read from the object, starting at `read_offset`.
+    read_buf: Vec<u8>,
+    read_offset: u64,
+
+    // Data written but not yet sent to the object, to be written at `write_offset`.
+    write_buf: Vec<u8>,
+    write_offset: u64,
+}
+
+impl ObjectHandle {
+    /// The name of the object this handle refers to.
+    pub fn object(&self) -> &str {
+        &self.object
+    }
+
+    /// The current position of the handle within the object.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// The size of the read and write buffers of this handle.
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    /// Set the size of the read and write buffers of this handle. Reads and writes at least this
+    /// large bypass the buffers entirely.
+    pub fn set_buffer_size(&mut self, buffer_size: usize) {
+        self.buffer_size = cmp::max(buffer_size, 1);
+    }
+
+    /// Get the size of the object, including any writes still buffered in this handle. An object
+    /// which does not exist yet has size zero.
+    pub fn size(&mut self) -> Result<u64> {
+        self.flush_writes()?;
+
+        match self.context.stat(&self.object) {
+            Ok(stat) => Ok(stat.size),
+            Err(Error(ErrorKind::Rados(err), _)) if err == libc::ENOENT as u32 => Ok(0),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Send any buffered writes to the object, and close the handle. Unlike dropping the handle,
+    /// this reports whether the buffered writes succeeded.
+    pub fn finish(mut self) -> Result<()> {
+        self.flush_writes()
+    }
+
+    /// Send any buffered writes to the object, using `Context::write`.
+    fn flush_writes(&mut self) -> Result<()> {
+        if !self.write_buf.is_empty() {
+            self.context
+                .write(&self.object, &self.write_buf, self.write_offset)?;
+            self.write_buf.clear();
+        }
+
+        Ok(())
+    }
+
+    /// The data in the read buffer from the current position onwards, if any.
+    fn buffered(&self) -> &[u8] {
+        let end = self.read_offset + self.read_buf.len() as u64;
+
+        if self.position >= self.read_offset && self.position < end {
+            &self.read_buf[(self.position - self.read_offset) as usize..]
+        } else {
+            &[]
+        }
+    }
+}
+
+impl Read for ObjectHandle {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Reads must see writes made through this handle.
+        self.flush_writes().map_err(errors::io_error)?;
+
+        if self.buffered().is_empty() {
+            if buf.len() >= self.buffer_size {
+                let read = self.context
+                    .read(&self.object, buf, self.position)
+                    .map_err(errors::io_error)?;
+                self.position += read as u64;
+
+                return Ok(read);
+            }
+
+            let mut read_buf = mem::replace(&mut self.read_buf, Vec::new());
+            read_buf.resize(self.buffer_size, 0);
+
+            let read = self.context
+                .read(&self.object, &mut read_buf, self.position)
+                .map_err(errors::io_error)?;
+            read_buf.truncate(read);
+
+            self.read_buf = read_buf;
+            self.read_offset = self.position;
+        }
+
+        let read = {
+            let buffered = self.buffered();
+            let read = cmp::min(buffered.len(), buf.len());
+            buf[..read].copy_from_slice(&buffered[..read]);
+            read
+        };
+        self.position += read as u64;
+
+        Ok(read)
+    }
+}
+
+impl Write for ObjectHandle {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Anything we have read ahead may be about to be overwritten.
+        self.read_buf.clear();
+
+        let contiguous = self.write_offset + self.write_buf.len() as u64 == self.position;
+
+        if !self.write_buf.is_empty()
+            && (!contiguous || self.write_buf.len() + buf.len() > self.buffer_size)
+        {
+            self.flush_writes().map_err(errors::io_error)?;
+        }
+
+        if buf.len() >= self.buffer_size {
+            self.context
+                .write(&self.object, buf, self.position)
+                .map_err(errors::io_error)?;
+        } else {
+            if self.write_buf.is_empty() {
+                self.write_offset = self.position;
+            }
+
+            self.write_buf.extend_from_slice(buf);
+        }
+
+        self.position += buf.len() as u64;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_writes().map_err(errors::io_error)
+    }
+}
+
+impl Seek for ObjectHandle {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => offset_by(self.position, delta),
+            SeekFrom::End(delta) => offset_by(self.size().map_err(errors::io_error)?, delta),
+        };
+
+        match position {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+impl Drop for ObjectHandle {
+    fn drop(&mut self) {
+        let _ = self.flush_writes();
+    }
+}
+
+/// Offset a position by a signed amount, as when seeking, returning `None` if the result would be
+/// negative or overflow.
+pub(super) fn offset_by(position: u64, delta: i64) -> Option<u64> {
+    if delta >= 0 {
+        position.checked_add(delta as u64)
+    } else {
+        position.checked_sub(delta.wrapping_neg() as u64)
+    }
+}
+
+impl Context {
+    /// Open a handle to the given object, which need not exist yet, for reading and writing it
+    /// through the standard `Read`, `Write` and `Seek` traits. The handle starts at the beginning
+    /// of the object, with buffers of `DEFAULT_BUFFER_SIZE` bytes.
+    ///
+    /// The handle uses its own I/O context, with the same pool, namespace, locator key, read
+    /// snapshot and write snapshot context as this one.
+    pub fn open(&mut self, obj: &str) -> Result<ObjectHandle> {
+        Ok(ObjectHandle {
+            context: self.duplicate()?,
+            object: obj.to_owned(),
+            position: 0,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            read_buf: Vec::new(),
+            read_offset: 0,
+            write_buf: Vec::new(),
+            write_offset: 0,
+        })
+    }
+}
@@ -0,0 +1,532 @@
+//! Listing the objects stored in a pool.
+
+use std::cmp;
+use std::ffi::CStr;
+use std::mem;
+use std::ptr;
+use std::result::Result as StdResult;
+use std::slice;
+use std::str;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError};
+use std::task::Poll;
+use std::thread;
+use std::vec;
+
+use ceph::rados::{self, rados_ioctx_t, rados_list_ctx_t};
+use libc;
+
+use async::{RadosStream, Wake, WakerSlot};
+use errors::{self, Error, Result};
+use ffi::{self, rados_object_list_cursor};
+
+use super::Context;
+
+/// A single object yielded from a pool listing.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ObjectEntry {
+    /// The name of the object.
+    pub name: String,
+
+    /// The locator key of the object, if one was set when it was written.
+    pub locator: Option<String>,
+
+    /// The namespace the object lives in. The default namespace is the empty string.
+    pub namespace: String,
+}
+
+impl ObjectEntry {
+    /// Copy an entry out of the NUL-terminated strings returned by `rados_nobjects_list_next`.
+    /// Any of the pointers may be null, in which case the corresponding field is empty.
+    unsafe fn from_raw(
+        name: *const libc::c_char,
+        locator: *const libc::c_char,
+        namespace: *const libc::c_char,
+    ) -> Result<ObjectEntry> {
+        unsafe fn owned(ptr: *const libc::c_char) -> Result<String> {
+            if ptr.is_null() {
+                Ok(String::new())
+            } else {
+                Ok(CStr::from_ptr(ptr).to_str()?.to_owned())
+            }
+        }
+
+        let locator = owned(locator)?;
+
+        Ok(ObjectEntry {
+            name: owned(name)?,
+            locator: if locator.is_empty() {
+                None
+            } else {
+                Some(locator)
+            },
+            namespace: owned(namespace)?,
+        })
+    }
+
+    /// Copy an entry out of a `rados_object_list_item`, whose strings are length-delimited
+    /// rather than NUL-terminated.
+    unsafe fn from_item(item: &ffi::rados_object_list_item) -> Result<ObjectEntry> {
+        unsafe fn owned(ptr: *const libc::c_char, len: usize) -> Result<String> {
+            if ptr.is_null() {
+                Ok(String::new())
+            } else {
+                let bytes = slice::from_raw_parts(ptr as *const u8, len);
+                Ok(str::from_utf8(bytes)?.to_owned())
+            }
+        }
+
+        let locator = owned(item.locator, item.locator_length)?;
+
+        Ok(ObjectEntry {
+            name: owned(item.oid, item.oid_length)?,
+            locator: if locator.is_empty() {
+                None
+            } else {
+                Some(locator)
+            },
+            namespace: owned(item.nspace, item.nspace_length)?,
+        })
+    }
+}
+
+/// A position in a pool listing, from which a listing may later be resumed using
+/// `Context::list_objects_from`.
+#[derive(Debug)]
+pub struct ObjectCursor {
+    handle: rados_object_list_cursor,
+
+    // The I/O context the cursor was allocated through, which it is freed through in turn.
+    io: rados_ioctx_t,
+
+    // Cursors handed out by `ObjectIter::cursor` may outlive the `Context` they were taken from,
+    // so they keep an I/O context of their own alive for `io` to point to.
+    _owner: Option<Context>,
+}
+
+// An `ObjectCursor` is an owned, heap-allocated object ID and is not tied to any thread.
+unsafe impl Send for ObjectCursor {}
+
+impl ObjectCursor {
+    /// Take ownership of a cursor allocated through the I/O context `io`, which must outlive the
+    /// returned `ObjectCursor`.
+    unsafe fn from_raw(io: rados_ioctx_t, handle: rados_object_list_cursor) -> ObjectCursor {
+        ObjectCursor {
+            handle,
+            io,
+            _owner: None,
+        }
+    }
+}
+
+impl Drop for ObjectCursor {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::rados_object_list_cursor_free(self.io, self.handle);
+        }
+    }
+}
+
+/// An iterator over the objects in a pool, wrapping a `rados_list_ctx_t`.
+///
+/// If opening the listing fails, the error is yielded as the first item of the iterator.
+#[derive(Debug)]
+pub struct ObjectIter<'a> {
+    list_res: StdResult<rados_list_ctx_t, Option<Error>>,
+    ctx: &'a mut Context,
+}
+
+impl<'a> ObjectIter<'a> {
+    fn open(ctx: &'a mut Context, cursor: Option<&ObjectCursor>) -> ObjectIter<'a> {
+        let mut list_handle = ptr::null_mut();
+
+        let list_res = errors::librados(unsafe {
+            rados::rados_nobjects_list_open(ctx.handle, &mut list_handle)
+        }).map(|()| {
+            if let Some(cursor) = cursor {
+                unsafe {
+                    ffi::rados_nobjects_list_seek_cursor(list_handle, cursor.handle);
+                }
+            }
+
+            list_handle
+        });
+
+        ObjectIter {
+            list_res: list_res.map_err(Some),
+            ctx,
+        }
+    }
+
+    /// Save the current position of the listing using `rados_nobjects_list_get_cursor`. The
+    /// next object this iterator would yield is the first object yielded when resuming from the
+    /// returned cursor.
+    ///
+    /// The cursor holds an I/O context of its own, so that it may outlive the `Context` it was
+    /// taken from.
+    pub fn cursor(&mut self) -> Result<ObjectCursor> {
+        match self.list_res {
+            Ok(list_handle) => {
+                let owner = self.ctx.duplicate()?;
+                let mut cursor_handle = ptr::null_mut();
+
+                errors::librados(unsafe {
+                    ffi::rados_nobjects_list_get_cursor(list_handle, &mut cursor_handle)
+                })?;
+
+                Ok(ObjectCursor {
+                    handle: cursor_handle,
+                    io: owner.handle,
+                    _owner: Some(owner),
+                })
+            }
+            Err(_) => bail!("cannot take a cursor from a listing which failed to open"),
+        }
+    }
+}
+
+impl<'a> Iterator for ObjectIter<'a> {
+    type Item = Result<ObjectEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.list_res {
+            Ok(list_handle) => {
+                let mut name = ptr::null();
+                let mut locator = ptr::null();
+                let mut namespace = ptr::null();
+
+                let err = unsafe {
+                    ffi::rados_nobjects_list_next(
+                        list_handle,
+                        &mut name,
+                        &mut locator,
+                        &mut namespace,
+                    )
+                };
+
+                // `ENOENT` signals the end of the listing rather than an error.
+                if err == -libc::ENOENT {
+                    return None;
+                }
+
+                Some(
+                    errors::librados(err)
+                        .and_then(|()| unsafe { ObjectEntry::from_raw(name, locator, namespace) }),
+                )
+            }
+            Err(ref mut error) => error.take().map(Err),
+        }
+    }
+}
+
+impl<'a> Drop for ObjectIter<'a> {
+    fn drop(&mut self) {
+        if let Ok(list_handle) = self.list_res {
+            unsafe {
+                rados::rados_nobjects_list_close(list_handle);
+            }
+        }
+    }
+}
+
+/// The number of objects fetched per `rados_object_list` call by an `ObjectLister`, unless
+/// overridden with `ObjectLister::set_batch_size`.
+pub const DEFAULT_LIST_BATCH_SIZE: usize = 1024;
+
+/// An iterator over the objects in a range of a pool's object space, owning its own I/O context
+/// so that it may be sent to another thread. Objects are fetched in batches using
+/// `rados_object_list`.
+///
+/// Listers covering disjoint ranges of a pool are created with `Context::split_objects`.
+#[derive(Debug)]
+pub struct ObjectLister {
+    next: ObjectCursor,
+    end: ObjectCursor,
+    batch_size: usize,
+    buffered: vec::IntoIter<ObjectEntry>,
+    done: bool,
+
+    // Fields are dropped in order, and the cursors must be freed before the I/O context they
+    // were allocated through is destroyed.
+    ctx: Context,
+}
+
+impl ObjectLister {
+    fn new(ctx: Context, start: ObjectCursor, end: ObjectCursor) -> ObjectLister {
+        ObjectLister {
+            next: start,
+            end,
+            batch_size: DEFAULT_LIST_BATCH_SIZE,
+            buffered: Vec::new().into_iter(),
+            done: false,
+            ctx,
+        }
+    }
+
+    /// Set the number of objects to fetch from the cluster at a time.
+    pub fn set_batch_size(&mut self, batch_size: usize) {
+        self.batch_size = cmp::max(batch_size, 1);
+    }
+
+    /// Turn this lister into a `Stream`, which fetches batches from a background thread.
+    pub fn into_stream(self) -> ObjectStream {
+        ObjectStream::spawn(Ok(self))
+    }
+
+    /// Whether or not the cursor has reached the end of this lister's range.
+    fn at_end(&self) -> bool {
+        unsafe {
+            ffi::rados_object_list_is_end(self.ctx.handle, self.next.handle) != 0
+                || ffi::rados_object_list_cursor_cmp(
+                    self.ctx.handle,
+                    self.next.handle,
+                    self.end.handle,
+                ) >= 0
+        }
+    }
+
+    /// Fetch the next batch of objects into `self.buffered`.
+    fn fetch(&mut self) -> Result<()> {
+        let mut items = (0..self.batch_size)
+            .map(|_| ffi::rados_object_list_item {
+                oid_length: 0,
+                oid: ptr::null_mut(),
+                nspace_length: 0,
+                nspace: ptr::null_mut(),
+                locator_length: 0,
+                locator: ptr::null_mut(),
+            })
+            .collect::<Vec<_>>();
+        let mut next_handle = ptr::null_mut();
+
+        let count = errors::librados_res(unsafe {
+            ffi::rados_object_list(
+                self.ctx.handle,
+                self.next.handle,
+                self.end.handle,
+                items.len(),
+                ptr::null(),
+                0,
+                items.as_mut_ptr(),
+                &mut next_handle,
+            )
+        })? as usize;
+
+        // Take ownership of the new cursor first, so that it is freed even if one of the
+        // entries fails to decode.
+        self.next = unsafe { ObjectCursor::from_raw(self.ctx.handle, next_handle) };
+
+        let entries = items[..count]
+            .iter()
+            .map(|item| unsafe { ObjectEntry::from_item(item) })
+            .collect::<Result<Vec<_>>>();
+
+        unsafe {
+            ffi::rados_object_list_free(count, items.as_mut_ptr());
+        }
+
+        self.buffered = entries?.into_iter();
+
+        Ok(())
+    }
+}
+
+impl Iterator for ObjectLister {
+    type Item = Result<ObjectEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.buffered.next() {
+                return Some(Ok(entry));
+            }
+
+            if self.done || self.at_end() {
+                self.done = true;
+                return None;
+            }
+
+            if let Err(error) = self.fetch() {
+                self.done = true;
+                return Some(Err(error));
+            }
+        }
+    }
+}
+
+/// A `Stream` of the objects in a pool.
+///
+/// Objects are fetched in batches by a background thread with its own I/O context, so polling
+/// the stream never blocks. At most one batch is fetched ahead of the consumer; dropping the
+/// stream stops the background thread once its current batch is finished.
+#[derive(Debug)]
+pub struct ObjectStream {
+    waker: Arc<WakerSlot>,
+    receiver: Receiver<Result<Vec<ObjectEntry>>>,
+    buffered: vec::IntoIter<ObjectEntry>,
+}
+
+impl ObjectStream {
+    fn spawn(lister: Result<ObjectLister>) -> ObjectStream {
+        let waker = Arc::new(WakerSlot::new());
+        let (sender, receiver) = mpsc::sync_channel(1);
+
+        match lister {
+            Ok(lister) => {
+                let waker = waker.clone();
+
+                thread::spawn(move || list_worker(lister, sender, waker));
+            }
+            // The channel has room for one message, so this cannot block. The sender is dropped
+            // immediately afterwards, ending the stream after the error.
+            Err(error) => mem::drop(sender.send(Err(error))),
+        }
+
+        ObjectStream {
+            waker,
+            receiver,
+            buffered: Vec::new().into_iter(),
+        }
+    }
+}
+
+/// The body of the background thread feeding an `ObjectStream`.
+fn list_worker(
+    mut lister: ObjectLister,
+    sender: SyncSender<Result<Vec<ObjectEntry>>>,
+    waker: Arc<WakerSlot>,
+) {
+    let batch_size = lister.batch_size;
+
+    loop {
+        let batch = lister
+            .by_ref()
+            .take(batch_size)
+            .collect::<Result<Vec<_>>>();
+
+        let last = match batch {
+            Ok(ref entries) => entries.len() < batch_size,
+            Err(_) => true,
+        };
+
+        // A failed send means the `ObjectStream` has been dropped, so there is no point in
+        // continuing the listing.
+        if sender.send(batch).is_err() {
+            break;
+        }
+
+        waker.wake();
+
+        if last {
+            break;
+        }
+    }
+
+    // Dropping the sender disconnects the channel; the stream has to be woken once more to see
+    // that it has ended.
+    mem::drop(sender);
+    waker.wake();
+}
+
+impl RadosStream for ObjectStream {
+    type Item = ObjectEntry;
+
+    fn poll_next_rados(&mut self, wake: &Wake) -> Poll<Option<Result<Self::Item>>> {
+        loop {
+            if let Some(entry) = self.buffered.next() {
+                return Poll::Ready(Some(Ok(entry)));
+            }
+
+            // The task must be registered before checking for a new batch, so that a batch sent
+            // in between cannot be missed.
+            self.waker.register(wake);
+
+            match self.receiver.try_recv() {
+                Ok(Ok(batch)) => self.buffered = batch.into_iter(),
+                Ok(Err(error)) => return Poll::Ready(Some(Err(error))),
+                Err(TryRecvError::Empty) => return Poll::Pending,
+                Err(TryRecvError::Disconnected) => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+rados_stream!(ObjectStream);
+
+impl Context {
+    /// List the objects in this context's pool using `rados_nobjects_list_open`.
+    pub fn list_objects(&mut self) -> ObjectIter {
+        ObjectIter::open(self, None)
+    }
+
+    /// Resume a listing of the objects in this context's pool from a cursor previously taken
+    /// with `ObjectIter::cursor`, using `rados_nobjects_list_seek_cursor`.
+    pub fn list_objects_from(&mut self, cursor: &ObjectCursor) -> ObjectIter {
+        ObjectIter::open(self, Some(cursor))
+    }
+
+    /// Asynchronously list the objects in this context's pool, fetching `batch_size` objects
+    /// at a time from a background thread.
+    pub fn list_objects_stream(&mut self, batch_size: usize) -> ObjectStream {
+        ObjectStream::spawn(self.object_lister().map(|mut lister| {
+            lister.set_batch_size(batch_size);
+            lister
+        }))
+    }
+
+    /// Create an `ObjectLister` covering every object in this context's pool.
+    pub fn object_lister(&mut self) -> Result<ObjectLister> {
+        let mut ctx = self.duplicate()?;
+        let (start, end) = ctx.object_list_bounds();
+
+        Ok(ObjectLister::new(ctx, start, end))
+    }
+
+    /// Split the objects in this context's pool into `n` disjoint ranges using
+    /// `rados_object_list_slice`, returning an independent `ObjectLister` for each. Together the
+    /// listers cover every object in the pool exactly once, and since each has its own I/O
+    /// context they may be sent to separate threads or turned into separate streams.
+    pub fn split_objects(&mut self, n: usize) -> Result<Vec<ObjectLister>> {
+        let (start, end) = self.object_list_bounds();
+
+        (0..n)
+            .map(|i| {
+                let ctx = self.duplicate()?;
+                let mut split_start = ptr::null_mut();
+                let mut split_end = ptr::null_mut();
+
+                unsafe {
+                    ffi::rados_object_list_slice(
+                        self.handle,
+                        start.handle,
+                        end.handle,
+                        i,
+                        n,
+                        &mut split_start,
+                        &mut split_end,
+                    );
+                }
+
+                // The split cursors belong to the lister, and so are freed through its context.
+                let (split_start, split_end) = unsafe {
+                    (
+                        ObjectCursor::from_raw(ctx.handle, split_start),
+                        ObjectCursor::from_raw(ctx.handle, split_end),
+                    )
+                };
+
+                Ok(ObjectLister::new(ctx, split_start, split_end))
+            })
+            .collect()
+    }
+
+    /// Fetch cursors to the beginning and end of this context's pool. The cursors must not
+    /// outlive this context.
+    fn object_list_bounds(&mut self) -> (ObjectCursor, ObjectCursor) {
+        unsafe {
+            (
+                ObjectCursor::from_raw(self.handle, ffi::rados_object_list_begin(self.handle)),
+                ObjectCursor::from_raw(self.handle, ffi::rados_object_list_end(self.handle)),
+            )
+        }
+    }
+}
@@ -0,0 +1,455 @@
+//! Advisory locks on RADOS objects. These locks are purely cooperative: they do not prevent any
+//! operation on the locked object, but only other attempts to take the same lock.
+
+use std::convert::TryFrom;
+use std::mem;
+use std::ptr;
+use std::str;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use ceph::rados;
+use libc;
+
+use errors::{self, Error, Result};
+
+use super::{Context, POOL};
+
+/// The flag passed to the lock functions to renew a lock already held under the same cookie, in
+/// place of failing with `EEXIST`. This is `LIBRADOS_LOCK_FLAG_RENEW` in `librados.h`.
+const LOCK_FLAG_RENEW: u8 = 1;
+
+/// A single holder of a lock, as reported by `rados_list_lockers`.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Locker {
+    /// The name of the client holding the lock, such as `client.4123`.
+    pub client: String,
+
+    /// The cookie the client took the lock with.
+    pub cookie: String,
+
+    /// The address of the client.
+    pub address: String,
+}
+
+/// The current state of a lock on an object.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LockInfo {
+    /// Whether the lock is held exclusively. If nobody holds the lock, this is meaningless.
+    pub exclusive: bool,
+
+    /// The tag of a shared lock; every shared holder must use the same tag.
+    pub tag: String,
+
+    /// Everybody currently holding the lock.
+    pub lockers: Vec<Locker>,
+}
+
+/// Everything needed to take, renew, and release a particular lock.
+#[derive(Debug)]
+struct LockSpec {
+    object: String,
+    name: String,
+    cookie: String,
+    description: String,
+
+    /// `None` for an exclusive lock, or the tag of a shared lock.
+    tag: Option<String>,
+    duration: Option<Duration>,
+}
+
+impl LockSpec {
+    fn lock(&self, context: &mut Context, flags: u8) -> Result<()> {
+        let object_id = POOL.get_str(&self.object)?;
+        let name = POOL.get_str(&self.name)?;
+        let cookie = POOL.get_str(&self.cookie)?;
+        let description = POOL.get_str(&self.description)?;
+
+        let mut timeval = self.duration.map(|duration| libc::timeval {
+            tv_sec: duration.as_secs() as libc::time_t,
+            tv_usec: (duration.subsec_nanos() / 1000) as libc::suseconds_t,
+        });
+        let timeval_ptr = timeval
+            .as_mut()
+            .map(|timeval| timeval as *mut libc::timeval)
+            .unwrap_or(ptr::null_mut());
+
+        match self.tag {
+            Some(ref tag) => {
+                let tag = POOL.get_str(tag)?;
+
+                errors::librados(unsafe {
+                    rados::rados_lock_shared(
+                        context.handle,
+                        object_id.as_ptr(),
+                        name.as_ptr(),
+                        cookie.as_ptr(),
+                        tag.as_ptr(),
+                        description.as_ptr(),
+                        timeval_ptr,
+                        flags,
+                    )
+                })?;
+
+                mem::drop(tag);
+            }
+            None => errors::librados(unsafe {
+                rados::rados_lock_exclusive(
+                    context.handle,
+                    object_id.as_ptr(),
+                    name.as_ptr(),
+                    cookie.as_ptr(),
+                    description.as_ptr(),
+                    timeval_ptr,
+                    flags,
+                )
+            })?,
+        }
+
+        mem::drop((object_id, name, cookie, description));
+
+        Ok(())
+    }
+
+    fn unlock(&self, context: &mut Context) -> Result<()> {
+        context.unlock(&self.object, &self.name, &self.cookie)
+    }
+}
+
+/// A background thread renewing a lock until told to stop.
+#[derive(Debug)]
+struct Renewal {
+    stop: Sender<()>,
+    thread: JoinHandle<()>,
+    error: Arc<Mutex<Option<Error>>>,
+}
+
+impl Renewal {
+    fn stop(self) {
+        mem::drop(self.stop);
+
+        // A panic in the renewal thread means the lock may not be renewed, but there's nothing
+        // more to do about it here.
+        let _ = self.thread.join();
+    }
+}
+
+/// A held lock on an object, which is released when the guard is dropped.
+///
+/// If the lock was taken with a duration, it expires unless renewed in time, either by calling
+/// `ObjectLockGuard::renew` or by starting background renewal with
+/// `ObjectLockGuard::renew_every`.
+#[derive(Debug)]
+pub struct ObjectLockGuard {
+    context: Context,
+    spec: Arc<LockSpec>,
+    renewal: Option<Renewal>,
+    released: bool,
+}
+
+impl ObjectLockGuard {
+    /// Renew the lock, extending its duration from now.
+    pub fn renew(&mut self) -> Result<()> {
+        self.spec.lock(&mut self.context, LOCK_FLAG_RENEW)
+    }
+
+    /// Start renewing the lock from a background thread every `interval`, which should be
+    /// comfortably shorter than the duration of the lock. If renewal fails, the thread stops and
+    /// the error is reported by `ObjectLockGuard::check_renewal`.
+    pub fn renew_every(&mut self, interval: Duration) -> Result<()> {
+        if let Some(renewal) = self.renewal.take() {
+            renewal.stop();
+        }
+
+        let mut context = self.context.duplicate()?;
+        let spec = self.spec.clone();
+        let error = Arc::new(Mutex::new(None));
+        let (stop, stopped) = mpsc::channel::<()>();
+
+        let thread = {
+            let error = error.clone();
+
+            thread::spawn(move || loop {
+                match stopped.recv_timeout(interval) {
+                    Err(RecvTimeoutError::Timeout) => {
+                        if let Err(renew_error) = spec.lock(&mut context, LOCK_FLAG_RENEW) {
+                            *error.lock().unwrap() = Some(renew_error);
+                            break;
+                        }
+                    }
+                    _ => break,
+                }
+            })
+        };
+
+        self.renewal = Some(Renewal {
+            stop,
+            thread,
+            error,
+        });
+
+        Ok(())
+    }
+
+    /// Check whether background renewal has failed, returning the error it failed with if so.
+    /// Once renewal has failed, the lock should be assumed lost.
+    pub fn check_renewal(&mut self) -> Result<()> {
+        match self.renewal {
+            Some(ref renewal) => match renewal.error.lock().unwrap().take() {
+                Some(error) => Err(error),
+                None => Ok(()),
+            },
+            None => Ok(()),
+        }
+    }
+
+    /// Release the lock, returning any error from `rados_unlock`. Dropping the guard does the
+    /// same, but ignores errors.
+    pub fn unlock(mut self) -> Result<()> {
+        self.release()
+    }
+
+    fn release(&mut self) -> Result<()> {
+        if let Some(renewal) = self.renewal.take() {
+            renewal.stop();
+        }
+
+        self.released = true;
+        self.spec.unlock(&mut self.context)
+    }
+}
+
+impl Drop for ObjectLockGuard {
+    fn drop(&mut self) {
+        if !self.released {
+            let _ = self.release();
+        }
+    }
+}
+
+impl Context {
+    fn lock_guard(&mut self, spec: LockSpec) -> Result<ObjectLockGuard> {
+        let mut context = self.duplicate()?;
+        spec.lock(&mut context, 0)?;
+
+        Ok(ObjectLockGuard {
+            context,
+            spec: Arc::new(spec),
+            renewal: None,
+            released: false,
+        })
+    }
+
+    /// Take an exclusive lock on an object, using `rados_lock_exclusive`. If `duration` is
+    /// `None`, the lock lasts until it is unlocked or broken. Fails with `EBUSY` if the lock is
+    /// held by anybody else, or `EEXIST` if it is already held under this cookie.
+    pub fn lock_exclusive(
+        &mut self,
+        obj: &str,
+        name: &str,
+        cookie: &str,
+        description: &str,
+        duration: Option<Duration>,
+    ) -> Result<()> {
+        let spec = LockSpec {
+            object: obj.to_owned(),
+            name: name.to_owned(),
+            cookie: cookie.to_owned(),
+            description: description.to_owned(),
+            tag: None,
+            duration,
+        };
+
+        spec.lock(self, 0)
+    }
+
+    /// Take a shared lock on an object, using `rados_lock_shared`. Any number of clients may
+    /// hold a shared lock at once, so long as they all use the same `tag`.
+    pub fn lock_shared(
+        &mut self,
+        obj: &str,
+        name: &str,
+        cookie: &str,
+        tag: &str,
+        description: &str,
+        duration: Option<Duration>,
+    ) -> Result<()> {
+        let spec = LockSpec {
+            object: obj.to_owned(),
+            name: name.to_owned(),
+            cookie: cookie.to_owned(),
+            description: description.to_owned(),
+            tag: Some(tag.to_owned()),
+            duration,
+        };
+
+        spec.lock(self, 0)
+    }
+
+    /// Take an exclusive lock on an object as with `Context::lock_exclusive`, returning a guard
+    /// which releases it when dropped.
+    pub fn lock_exclusive_guard(
+        &mut self,
+        obj: &str,
+        name: &str,
+        cookie: &str,
+        description: &str,
+        duration: Option<Duration>,
+    ) -> Result<ObjectLockGuard> {
+        self.lock_guard(LockSpec {
+            object: obj.to_owned(),
+            name: name.to_owned(),
+            cookie: cookie.to_owned(),
+            description: description.to_owned(),
+            tag: None,
+            duration,
+        })
+    }
+
+    /// Take a shared lock on an object as with `Context::lock_shared`, returning a guard which
+    /// releases it when dropped.
+    pub fn lock_shared_guard(
+        &mut self,
+        obj: &str,
+        name: &str,
+        cookie: &str,
+        tag: &str,
+        description: &str,
+        duration: Option<Duration>,
+    ) -> Result<ObjectLockGuard> {
+        self.lock_guard(LockSpec {
+            object: obj.to_owned(),
+            name: name.to_owned(),
+            cookie: cookie.to_owned(),
+            description: description.to_owned(),
+            tag: Some(tag.to_owned()),
+            duration,
+        })
+    }
+
+    /// Release a lock held by this client under the given cookie, using `rados_unlock`.
+    pub fn unlock(&mut self, obj: &str, name: &str, cookie: &str) -> Result<()> {
+        let object_id = POOL.get_str(obj)?;
+        let name = POOL.get_str(name)?;
+        let cookie = POOL.get_str(cookie)?;
+
+        errors::librados(unsafe {
+            rados::rados_unlock(
+                self.handle,
+                object_id.as_ptr(),
+                name.as_ptr(),
+                cookie.as_ptr(),
+            )
+        })?;
+
+        mem::drop((object_id, name, cookie));
+
+        Ok(())
+    }
+
+    /// Forcibly release a lock held by another client, using `rados_break_lock`. The `client`
+    /// and `cookie` are as reported by `Context::list_lockers`.
+    pub fn break_lock(&mut self, obj: &str, name: &str, client: &str, cookie: &str) -> Result<()> {
+        let object_id = POOL.get_str(obj)?;
+        let name = POOL.get_str(name)?;
+        let client = POOL.get_str(client)?;
+        let cookie = POOL.get_str(cookie)?;
+
+        errors::librados(unsafe {
+            rados::rados_break_lock(
+                self.handle,
+                object_id.as_ptr(),
+                name.as_ptr(),
+                client.as_ptr(),
+                cookie.as_ptr(),
+            )
+        })?;
+
+        mem::drop((object_id, name, client, cookie));
+
+        Ok(())
+    }
+
+    /// List the holders of a lock, using `rados_list_lockers`.
+    pub fn list_lockers(&mut self, obj: &str, name: &str) -> Result<LockInfo> {
+        let object_id = POOL.get_str(obj)?;
+        let name = POOL.get_str(name)?;
+
+        let mut exclusive = 0;
+        let mut tag = vec![0u8; 256];
+        let mut clients = vec![0u8; 1024];
+        let mut cookies = vec![0u8; 1024];
+        let mut addresses = vec![0u8; 1024];
+
+        let count = loop {
+            let mut tag_len = tag.len();
+            let mut clients_len = clients.len();
+            let mut cookies_len = cookies.len();
+            let mut addresses_len = addresses.len();
+
+            let res = unsafe {
+                rados::rados_list_lockers(
+                    self.handle,
+                    object_id.as_ptr(),
+                    name.as_ptr(),
+                    &mut exclusive,
+                    tag.as_mut_ptr() as *mut libc::c_char,
+                    &mut tag_len,
+                    clients.as_mut_ptr() as *mut libc::c_char,
+                    &mut clients_len,
+                    cookies.as_mut_ptr() as *mut libc::c_char,
+                    &mut cookies_len,
+                    addresses.as_mut_ptr() as *mut libc::c_char,
+                    &mut addresses_len,
+                )
+            };
+
+            // The result is a `ssize_t`, which must be checked to fit before it is narrowed.
+            let res = match libc::c_int::try_from(res) {
+                Ok(res) => res,
+                Err(_) => bail!("invalid return value {} from rados_list_lockers", res),
+            };
+
+            // On `ERANGE`, librados reports the lengths it needs.
+            if res == -libc::ERANGE {
+                tag.resize(tag_len, 0);
+                clients.resize(clients_len, 0);
+                cookies.resize(cookies_len, 0);
+                addresses.resize(addresses_len, 0);
+            } else {
+                break errors::librados_res(res)? as usize;
+            }
+        };
+
+        mem::drop((object_id, name));
+
+        // Each buffer holds one NUL-terminated string per locker.
+        fn split(buf: &[u8], count: usize) -> Result<Vec<String>> {
+            buf.split(|&b| b == 0)
+                .take(count)
+                .map(|s| Ok(str::from_utf8(s)?.to_owned()))
+                .collect()
+        }
+
+        let clients = split(&clients, count)?;
+        let cookies = split(&cookies, count)?;
+        let addresses = split(&addresses, count)?;
+
+        Ok(LockInfo {
+            exclusive: exclusive != 0,
+            tag: split(&tag, 1)?.pop().unwrap_or_default(),
+            lockers: clients
+                .into_iter()
+                .zip(cookies)
+                .zip(addresses)
+                .map(|((client, cookie), address)| Locker {
+                    client,
+                    cookie,
+                    address,
+                })
+                .collect(),
+        })
+    }
+}
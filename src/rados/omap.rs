@@ -0,0 +1,146 @@
+//! Reading and writing the omap (object map) of RADOS objects: a sorted key/value store attached
+//! to each object, separate from its data and extended attributes.
+//!
+//! Note that the librados C API provides no way to read or write an omap *header*, so omap
+//! headers are not supported.
+
+use std::collections::BTreeMap;
+
+use errors::Result;
+
+use super::Context;
+use super::ops::{OmapPage, OutputFuture, WriteOp, WriteOpFuture};
+
+impl Context {
+    /// Set key/value pairs in an object's omap, creating the object if it does not exist, using
+    /// `rados_write_op_omap_set`.
+    pub fn omap_set<I, K, V>(&mut self, obj: &str, entries: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<[u8]>,
+    {
+        let op = WriteOp::new().omap_set(entries)?;
+        self.operate(obj, op)
+    }
+
+    /// Fetch the values of specific keys from an object's omap, using
+    /// `rados_read_op_omap_get_vals_by_keys`. Keys which are not present are left out of the
+    /// result.
+    pub fn omap_get<I, K>(&mut self, obj: &str, keys: I) -> Result<BTreeMap<String, Vec<u8>>>
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<str>,
+    {
+        self.read_output(obj, |op| op.omap_vals_by_keys(keys))
+    }
+
+    /// List up to `max` keys of an object's omap, starting after the key `start_after` (or from
+    /// the beginning, if it is empty), using `rados_read_op_omap_get_keys2`. To fetch the next
+    /// page, pass the last key of this one as `start_after`.
+    pub fn omap_keys(
+        &mut self,
+        obj: &str,
+        start_after: &str,
+        max: u64,
+    ) -> Result<OmapPage<String>> {
+        self.read_output(obj, |op| op.omap_keys(start_after, max))
+    }
+
+    /// List up to `max` key/value pairs of an object's omap, starting after the key
+    /// `start_after` and only including keys beginning with `prefix`, using
+    /// `rados_read_op_omap_get_vals2`. Either string may be empty.
+    pub fn omap_vals(
+        &mut self,
+        obj: &str,
+        start_after: &str,
+        prefix: &str,
+        max: u64,
+    ) -> Result<OmapPage<(String, Vec<u8>)>> {
+        self.read_output(obj, |op| op.omap_vals(start_after, prefix, max))
+    }
+
+    /// Remove keys from an object's omap, using `rados_write_op_omap_rm_keys`.
+    pub fn omap_rm_keys<I, K>(&mut self, obj: &str, keys: I) -> Result<()>
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<str>,
+    {
+        let op = WriteOp::new().omap_rm_keys(keys)?;
+        self.operate(obj, op)
+    }
+
+    /// Remove every key from an object's omap, using `rados_write_op_omap_clear`.
+    pub fn omap_clear(&mut self, obj: &str) -> Result<()> {
+        let op = WriteOp::new().omap_clear();
+        self.operate(obj, op)
+    }
+
+    /// Asynchronously set key/value pairs in an object's omap, using
+    /// `rados_aio_write_op_operate`.
+    pub fn omap_set_async<I, K, V>(&mut self, obj: &str, entries: I) -> WriteOpFuture
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<[u8]>,
+    {
+        match WriteOp::new().omap_set(entries) {
+            Ok(op) => self.operate_async(obj, op),
+            Err(error) => WriteOpFuture::failed(error),
+        }
+    }
+
+    /// Asynchronously fetch the values of specific keys from an object's omap, using
+    /// `rados_aio_read_op_operate`.
+    pub fn omap_get_async<I, K>(
+        &mut self,
+        obj: &str,
+        keys: I,
+    ) -> OutputFuture<BTreeMap<String, Vec<u8>>>
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<str>,
+    {
+        self.read_output_async(obj, |op| op.omap_vals_by_keys(keys))
+    }
+
+    /// Asynchronously list up to `max` keys of an object's omap, starting after `start_after`.
+    pub fn omap_keys_async(
+        &mut self,
+        obj: &str,
+        start_after: &str,
+        max: u64,
+    ) -> OutputFuture<OmapPage<String>> {
+        self.read_output_async(obj, |op| op.omap_keys(start_after, max))
+    }
+
+    /// Asynchronously list up to `max` key/value pairs of an object's omap, starting after
+    /// `start_after` and only including keys beginning with `prefix`.
+    pub fn omap_vals_async(
+        &mut self,
+        obj: &str,
+        start_after: &str,
+        prefix: &str,
+        max: u64,
+    ) -> OutputFuture<OmapPage<(String, Vec<u8>)>> {
+        self.read_output_async(obj, |op| op.omap_vals(start_after, prefix, max))
+    }
+
+    /// Asynchronously remove keys from an object's omap.
+    pub fn omap_rm_keys_async<I, K>(&mut self, obj: &str, keys: I) -> WriteOpFuture
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<str>,
+    {
+        match WriteOp::new().omap_rm_keys(keys) {
+            Ok(op) => self.operate_async(obj, op),
+            Err(error) => WriteOpFuture::failed(error),
+        }
+    }
+
+    /// Asynchronously remove every key from an object's omap.
+    pub fn omap_clear_async(&mut self, obj: &str) -> WriteOpFuture {
+        let op = WriteOp::new().omap_clear();
+        self.operate_async(obj, op)
+    }
+}
@@ -0,0 +1,1453 @@
+//! Compound operations on a single RADOS object, wrapping `rados_write_op_t` and
+//! `rados_read_op_t`. All of the steps in a compound operation are applied atomically.
+
+use std::collections::BTreeMap;
+use std::ffi::{CStr, CString};
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::Poll;
+
+use ceph::rados::{self, rados_omap_iter_t, rados_read_op_t, rados_write_op_t,
+                  rados_xattrs_iter_t};
+use chrono::{DateTime, Local, TimeZone, Utc};
+use libc;
+
+use async::{RadosFuture, Wake};
+use errors::{self, Error, Result};
+use ffi;
+
+use super::{utc_timestamp, Context, DataFuture, Stat, Stat2, UnitFuture, VersionFuture, POOL};
+
+/// Convert an iterator of keys into `CString`s, for passing to the librados omap functions.
+fn key_cstrings<I, K>(keys: I) -> Result<Vec<CString>>
+where
+    I: IntoIterator<Item = K>,
+    K: AsRef<str>,
+{
+    keys.into_iter()
+        .map(|key| Ok(CString::new(key.as_ref())?))
+        .collect()
+}
+
+/// The conversion to apply to errors from executing a compound operation: comparison guard
+/// failures are always decoded, and extent mismatches only if the operation contains a `cmp_ext`
+/// step, as otherwise the error code has no such meaning.
+fn op_map_err(cmpext: bool) -> fn(Error) -> Error {
+    if cmpext {
+        |error| errors::comparison(errors::cmpext(error))
+    } else {
+        errors::comparison
+    }
+}
+
+/// A comparison operator, used by guard steps such as `WriteOp::cmp_xattr`. Comparisons are of
+/// the form `stored <op> given`.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+}
+
+impl CmpOp {
+    /// The `LIBRADOS_CMPXATTR_OP_*` value corresponding to this operator.
+    fn as_raw(self) -> u8 {
+        match self {
+            CmpOp::Eq => 1,
+            CmpOp::Ne => 2,
+            CmpOp::Gt => 3,
+            CmpOp::Gte => 4,
+            CmpOp::Lt => 5,
+            CmpOp::Lte => 6,
+        }
+    }
+
+    /// The raw value of this operator for an omap comparison. The OSD only implements `Eq`, `Lt`
+    /// and `Gt` for omap comparisons, so any other operator is refused here rather than failing
+    /// with `EINVAL` once the operation is executed.
+    fn as_omap_raw(self) -> Result<u8> {
+        match self {
+            CmpOp::Eq | CmpOp::Gt | CmpOp::Lt => Ok(self.as_raw()),
+            _ => bail!("omap comparisons only support `Eq`, `Lt` and `Gt`, not `{:?}`", self),
+        }
+    }
+}
+
+/// A compound write operation on a single object, wrapping a `rados_write_op_t`. Steps are added
+/// in a chain and the operation is then executed atomically with `Context::operate` or
+/// `Context::operate_async`: either every step is applied, or none are.
+///
+/// Guard steps such as `assert_exists` and `cmp_xattr` cause the whole operation to fail if
+/// their condition does not hold.
+///
+/// ```rust,no_run
+/// # extern crate rad;
+/// # fn dummy(pool: &mut rad::Context) -> ::rad::Result<()> {
+/// use rad::WriteOp;
+///
+/// let op = WriteOp::new()
+///     .create(true)
+///     .write_full(b"contents")
+///     .set_xattr("owner", b"me")?
+///     .omap_set(vec![("created-by", "rad")])?;
+///
+/// pool.operate("object-name", op)?;
+/// # Ok(()) } fn main() {}
+/// ```
+#[derive(Debug)]
+pub struct WriteOp {
+    handle: rados_write_op_t,
+
+    // Whether the operation contains a `cmp_ext` step, whose failure needs decoding.
+    cmpext: bool,
+}
+
+// A `rados_write_op_t` is not tied to the thread it was created on.
+unsafe impl Send for WriteOp {}
+
+impl Drop for WriteOp {
+    fn drop(&mut self) {
+        unsafe {
+            rados::rados_release_write_op(self.handle);
+        }
+    }
+}
+
+impl WriteOp {
+    /// Create a new, empty write operation using `rados_create_write_op`.
+    pub fn new() -> WriteOp {
+        WriteOp {
+            handle: unsafe { rados::rados_create_write_op() },
+            cmpext: false,
+        }
+    }
+
+    /// The conversion to apply to errors from executing this operation.
+    fn map_err(&self) -> fn(Error) -> Error {
+        op_map_err(self.cmpext)
+    }
+
+    /// Create the object, using `rados_write_op_create`. If `exclusive` is set, the operation
+    /// fails with `EEXIST` if the object already exists.
+    pub fn create(self, exclusive: bool) -> WriteOp {
+        unsafe {
+            rados::rados_write_op_create(self.handle, exclusive as libc::c_int, ptr::null());
+        }
+
+        self
+    }
+
+    /// Write a buffer to the object at the given offset, using `rados_write_op_write`.
+    pub fn write(self, buf: &[u8], offset: u64) -> WriteOp {
+        unsafe {
+            rados::rados_write_op_write(
+                self.handle,
+                buf.as_ptr() as *const libc::c_char,
+                buf.len(),
+                offset,
+            );
+        }
+
+        self
+    }
+
+    /// Replace the contents of the object with a buffer, using `rados_write_op_write_full`.
+    pub fn write_full(self, buf: &[u8]) -> WriteOp {
+        unsafe {
+            rados::rados_write_op_write_full(
+                self.handle,
+                buf.as_ptr() as *const libc::c_char,
+                buf.len(),
+            );
+        }
+
+        self
+    }
+
+    /// Append a buffer to the object, using `rados_write_op_append`.
+    pub fn append(self, buf: &[u8]) -> WriteOp {
+        unsafe {
+            rados::rados_write_op_append(
+                self.handle,
+                buf.as_ptr() as *const libc::c_char,
+                buf.len(),
+            );
+        }
+
+        self
+    }
+
+    /// Resize the object, filling with zeroes if necessary, using `rados_write_op_truncate`.
+    pub fn truncate(self, size: u64) -> WriteOp {
+        unsafe {
+            rados::rados_write_op_truncate(self.handle, size);
+        }
+
+        self
+    }
+
+    /// Zero out a range of the object, using `rados_write_op_zero`.
+    pub fn zero(self, offset: u64, len: u64) -> WriteOp {
+        unsafe {
+            rados::rados_write_op_zero(self.handle, offset, len);
+        }
+
+        self
+    }
+
+    /// Remove the object, using `rados_write_op_remove`.
+    pub fn remove(self) -> WriteOp {
+        unsafe {
+            rados::rados_write_op_remove(self.handle);
+        }
+
+        self
+    }
+
+    /// Set an extended attribute on the object, using `rados_write_op_setxattr`.
+    pub fn set_xattr(self, key: &str, value: &[u8]) -> Result<WriteOp> {
+        let key_cstr = POOL.get_str(key)?;
+
+        unsafe {
+            rados::rados_write_op_setxattr(
+                self.handle,
+                key_cstr.as_ptr(),
+                value.as_ptr() as *const libc::c_char,
+                value.len(),
+            );
+        }
+
+        mem::drop(key_cstr);
+
+        Ok(self)
+    }
+
+    /// Remove an extended attribute from the object, using `rados_write_op_rmxattr`.
+    pub fn rm_xattr(self, key: &str) -> Result<WriteOp> {
+        let key_cstr = POOL.get_str(key)?;
+
+        unsafe {
+            rados::rados_write_op_rmxattr(self.handle, key_cstr.as_ptr());
+        }
+
+        mem::drop(key_cstr);
+
+        Ok(self)
+    }
+
+    /// Guard the operation on the object existing, using `rados_write_op_assert_exists`. If it
+    /// does not, the operation fails with `ENOENT`.
+    pub fn assert_exists(self) -> WriteOp {
+        unsafe {
+            rados::rados_write_op_assert_exists(self.handle);
+        }
+
+        self
+    }
+
+    /// Guard the operation on the object's version being exactly `version`, using
+    /// `rados_write_op_assert_version`. If the object is newer (`version` is too low), the
+    /// operation fails with `ERANGE`; if it is older (`version` is too high), with `EOVERFLOW`.
+    pub fn assert_version(self, version: u64) -> WriteOp {
+        unsafe {
+            rados::rados_write_op_assert_version(self.handle, version);
+        }
+
+        self
+    }
+
+    /// Guard the operation on a comparison between the value of one of the object's extended
+    /// attributes and a given value, using `rados_write_op_cmpxattr`. If the comparison does
+    /// not hold, the operation fails with `ErrorKind::ComparisonFailed`.
+    pub fn cmp_xattr(self, key: &str, op: CmpOp, value: &[u8]) -> Result<WriteOp> {
+        let key_cstr = POOL.get_str(key)?;
+
+        unsafe {
+            rados::rados_write_op_cmpxattr(
+                self.handle,
+                key_cstr.as_ptr(),
+                op.as_raw(),
+                value.as_ptr() as *const libc::c_char,
+                value.len(),
+            );
+        }
+
+        mem::drop(key_cstr);
+
+        Ok(self)
+    }
+
+    /// Guard the operation on the object's data at `offset` matching `buf`, using
+    /// `rados_write_op_cmpext`. If it does not, the operation fails with
+    /// `ErrorKind::ExtentMismatch`, carrying the offset of the first mismatched byte relative to
+    /// `offset`.
+    pub fn cmp_ext(mut self, buf: &[u8], offset: u64) -> WriteOp {
+        unsafe {
+            ffi::rados_write_op_cmpext(
+                self.handle,
+                buf.as_ptr() as *const libc::c_char,
+                buf.len(),
+                offset,
+                ptr::null_mut(),
+            );
+        }
+
+        self.cmpext = true;
+
+        self
+    }
+
+    /// Guard the operation on a comparison between the value of a key in the object's omap and a
+    /// given value, using `rados_write_op_omap_cmp`. If the comparison does not hold, the
+    /// operation fails with `ErrorKind::ComparisonFailed`. Only `CmpOp::Eq`, `CmpOp::Lt` and
+    /// `CmpOp::Gt` are supported for omap comparisons; any other operator is an error.
+    pub fn omap_cmp(self, key: &str, op: CmpOp, value: &[u8]) -> Result<WriteOp> {
+        let op = op.as_omap_raw()?;
+        let key_cstr = POOL.get_str(key)?;
+
+        unsafe {
+            rados::rados_write_op_omap_cmp(
+                self.handle,
+                key_cstr.as_ptr(),
+                op,
+                value.as_ptr() as *const libc::c_char,
+                value.len(),
+                ptr::null_mut(),
+            );
+        }
+
+        mem::drop(key_cstr);
+
+        Ok(self)
+    }
+
+    /// Set key/value pairs in the object's omap, using `rados_write_op_omap_set`.
+    pub fn omap_set<I, K, V>(self, entries: I) -> Result<WriteOp>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<[u8]>,
+    {
+        let (keys, values): (Vec<_>, Vec<_>) = entries.into_iter().unzip();
+        let keys = key_cstrings(keys)?;
+
+        let key_ptrs = keys.iter().map(|key| key.as_ptr()).collect::<Vec<_>>();
+        let value_ptrs = values
+            .iter()
+            .map(|value| value.as_ref().as_ptr() as *const libc::c_char)
+            .collect::<Vec<_>>();
+        let value_lens = values
+            .iter()
+            .map(|value| value.as_ref().len())
+            .collect::<Vec<_>>();
+
+        unsafe {
+            rados::rados_write_op_omap_set(
+                self.handle,
+                key_ptrs.as_ptr(),
+                value_ptrs.as_ptr(),
+                value_lens.as_ptr(),
+                keys.len(),
+            );
+        }
+
+        Ok(self)
+    }
+
+    /// Remove keys from the object's omap, using `rados_write_op_omap_rm_keys`.
+    pub fn omap_rm_keys<I, K>(self, keys: I) -> Result<WriteOp>
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<str>,
+    {
+        let keys = key_cstrings(keys)?;
+        let key_ptrs = keys.iter().map(|key| key.as_ptr()).collect::<Vec<_>>();
+
+        unsafe {
+            rados::rados_write_op_omap_rm_keys(self.handle, key_ptrs.as_ptr(), keys.len());
+        }
+
+        Ok(self)
+    }
+
+    /// Remove every key from the object's omap, using `rados_write_op_omap_clear`.
+    pub fn omap_clear(self) -> WriteOp {
+        unsafe {
+            rados::rados_write_op_omap_clear(self.handle);
+        }
+
+        self
+    }
+}
+
+impl Default for WriteOp {
+    fn default() -> WriteOp {
+        WriteOp::new()
+    }
+}
+
+/// A page of results from listing an object's omap.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OmapPage<T> {
+    /// The entries in this page, in ascending order of key.
+    pub entries: Vec<T>,
+
+    /// Whether or not there are more entries after the last one in this page.
+    pub more: bool,
+}
+
+/// Storage for the result of a single step of a `ReadOp`, which librados fills in when the
+/// operation completes.
+#[derive(Debug)]
+enum Slot {
+    Read {
+        buf: Vec<u8>,
+        bytes_read: libc::size_t,
+        prval: libc::c_int,
+    },
+    Stat {
+        size: u64,
+        mtime: libc::time_t,
+        prval: libc::c_int,
+
+        // Filled in from the version of the whole operation once it completes.
+        version: u64,
+    },
+    Stat2 {
+        size: u64,
+        mtime: ffi::timespec,
+        prval: libc::c_int,
+        version: u64,
+    },
+    Xattrs {
+        iter: rados_xattrs_iter_t,
+        prval: libc::c_int,
+    },
+    Omap {
+        iter: rados_omap_iter_t,
+        more: libc::c_uchar,
+        prval: libc::c_int,
+    },
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        match *self {
+            Slot::Xattrs { iter, .. } => if !iter.is_null() {
+                unsafe {
+                    rados::rados_getxattrs_end(iter);
+                }
+            },
+            Slot::Omap { iter, .. } => if !iter.is_null() {
+                unsafe {
+                    rados::rados_omap_get_end(iter);
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+/// The error returned when an `Output` is redeemed against a `ReadOp` it did not come from.
+fn wrong_slot<T>() -> Result<T> {
+    bail!("output handle does not belong to this read operation")
+}
+
+/// Drain a `rados_xattrs_iter_t` into a map of extended attribute names to values.
+unsafe fn drain_xattrs(iter: rados_xattrs_iter_t) -> Result<BTreeMap<String, Vec<u8>>> {
+    let mut xattrs = BTreeMap::new();
+
+    loop {
+        let mut name = ptr::null();
+        let mut value = ptr::null();
+        let mut len = 0;
+
+        errors::librados(rados::rados_getxattrs_next(iter, &mut name, &mut value, &mut len))?;
+
+        if name.is_null() {
+            break;
+        }
+
+        let name = CStr::from_ptr(name).to_str()?.to_owned();
+        let value = if value.is_null() {
+            Vec::new()
+        } else {
+            slice::from_raw_parts(value as *const u8, len).to_vec()
+        };
+
+        xattrs.insert(name, value);
+    }
+
+    Ok(xattrs)
+}
+
+/// Drain a `rados_omap_iter_t` into a list of key/value pairs.
+unsafe fn drain_omap(iter: rados_omap_iter_t) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut entries = Vec::new();
+
+    loop {
+        let mut key = ptr::null_mut();
+        let mut value = ptr::null_mut();
+        let mut len = 0;
+
+        errors::librados(rados::rados_omap_get_next(iter, &mut key, &mut value, &mut len))?;
+
+        if key.is_null() {
+            break;
+        }
+
+        let key = CStr::from_ptr(key).to_str()?.to_owned();
+        let value = if value.is_null() {
+            Vec::new()
+        } else {
+            slice::from_raw_parts(value as *const u8, len).to_vec()
+        };
+
+        entries.push((key, value));
+    }
+
+    Ok(entries)
+}
+
+fn take_read(slot: &mut Slot) -> Result<Vec<u8>> {
+    match *slot {
+        Slot::Read {
+            ref mut buf,
+            bytes_read,
+            prval,
+        } => {
+            errors::librados(prval)?;
+
+            let mut buf = mem::replace(buf, Vec::new());
+            buf.truncate(bytes_read);
+
+            Ok(buf)
+        }
+        _ => wrong_slot(),
+    }
+}
+
+fn take_stat(slot: &mut Slot) -> Result<Stat> {
+    match *slot {
+        Slot::Stat {
+            size,
+            mtime,
+            prval,
+            version,
+        } => {
+            errors::librados(prval)?;
+
+            Ok(Stat {
+                size,
+                last_modified: Local.timestamp(mtime, 0),
+                version,
+            })
+        }
+        _ => wrong_slot(),
+    }
+}
+
+fn take_stat2(slot: &mut Slot) -> Result<Stat2> {
+    match *slot {
+        Slot::Stat2 {
+            size,
+            mtime,
+            prval,
+            version,
+        } => {
+            errors::librados(prval)?;
+
+            Ok(Stat2 {
+                size,
+                last_modified: utc_timestamp(mtime),
+                version,
+            })
+        }
+        _ => wrong_slot(),
+    }
+}
+
+fn take_xattrs(slot: &mut Slot) -> Result<BTreeMap<String, Vec<u8>>> {
+    match *slot {
+        Slot::Xattrs { iter, prval } => {
+            errors::librados(prval)?;
+
+            unsafe { drain_xattrs(iter) }
+        }
+        _ => wrong_slot(),
+    }
+}
+
+fn take_omap_vals(slot: &mut Slot) -> Result<OmapPage<(String, Vec<u8>)>> {
+    match *slot {
+        Slot::Omap { iter, more, prval } => {
+            errors::librados(prval)?;
+
+            Ok(OmapPage {
+                entries: unsafe { drain_omap(iter)? },
+                more: more != 0,
+            })
+        }
+        _ => wrong_slot(),
+    }
+}
+
+fn take_omap_keys(slot: &mut Slot) -> Result<OmapPage<String>> {
+    take_omap_vals(slot).map(|page| OmapPage {
+        entries: page.entries.into_iter().map(|(key, _)| key).collect(),
+        more: page.more,
+    })
+}
+
+fn take_omap_map(slot: &mut Slot) -> Result<BTreeMap<String, Vec<u8>>> {
+    take_omap_vals(slot).map(|page| page.entries.into_iter().collect())
+}
+
+/// A handle to the result of a single step of a `ReadOp`, which can be redeemed with
+/// `CompletedReadOp::take` once the operation has completed.
+#[derive(Debug)]
+pub struct Output<T> {
+    op_id: usize,
+    index: usize,
+    take: fn(&mut Slot) -> Result<T>,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Output<T> {
+    fn new(op_id: usize, index: usize, take: fn(&mut Slot) -> Result<T>) -> Output<T> {
+        Output {
+            op_id,
+            index,
+            take,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// Distinguishes the `ReadOp`s created by this process.
+static READ_OP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A compound read operation on a single object, wrapping a `rados_read_op_t`. Every step is
+/// performed in a single round trip to the cluster by `Context::read_op` or
+/// `Context::read_op_async`.
+///
+/// Steps which produce a result return an `Output` handle, which is redeemed for the step's
+/// result from the `CompletedReadOp` once the operation has completed. Guard steps such as
+/// `assert_version` cause the whole operation to fail if their condition does not hold.
+///
+/// ```rust,no_run
+/// # extern crate rad;
+/// # fn dummy(pool: &mut rad::Context) -> ::rad::Result<()> {
+/// use rad::ReadOp;
+///
+/// let mut op = ReadOp::new();
+/// let stat = op.stat();
+/// let header = op.read(0, 512);
+/// let xattrs = op.xattrs();
+///
+/// let mut completed = pool.read_op("object-name", op)?;
+///
+/// println!("size: {}", completed.take(stat)?.size);
+/// println!("header: {:?}", completed.take(header)?);
+/// println!("xattrs: {:?}", completed.take(xattrs)?);
+/// # Ok(()) } fn main() {}
+/// ```
+#[derive(Debug)]
+pub struct ReadOp {
+    handle: rados_read_op_t,
+
+    // Identifies this operation, so that `Output` handles from other operations are refused.
+    id: usize,
+
+    // Whether the operation contains a `cmp_ext` step, whose failure needs decoding.
+    cmpext: bool,
+
+    // Each slot is boxed so that the pointers into it handed to librados remain valid as more
+    // steps are added.
+    slots: Vec<Box<Slot>>,
+}
+
+// A `rados_read_op_t` is not tied to the thread it was created on, and the slots are only ever
+// written to by librados while the operation is in flight.
+unsafe impl Send for ReadOp {}
+
+impl Drop for ReadOp {
+    fn drop(&mut self) {
+        unsafe {
+            rados::rados_release_read_op(self.handle);
+        }
+    }
+}
+
+impl ReadOp {
+    /// Create a new, empty read operation using `rados_create_read_op`.
+    pub fn new() -> ReadOp {
+        ReadOp {
+            handle: unsafe { rados::rados_create_read_op() },
+            id: READ_OP_COUNTER.fetch_add(1, Ordering::Relaxed),
+            cmpext: false,
+            slots: Vec::new(),
+        }
+    }
+
+    /// The conversion to apply to errors from executing this operation.
+    fn map_err(&self) -> fn(Error) -> Error {
+        op_map_err(self.cmpext)
+    }
+
+    /// Add a slot for a step's result, returning its index and a reference to it. The slot is
+    /// boxed, so pointers into it remain valid for the lifetime of this `ReadOp`.
+    fn push_slot(&mut self, slot: Slot) -> (usize, &mut Slot) {
+        self.slots.push(Box::new(slot));
+
+        let index = self.slots.len() - 1;
+
+        (index, &mut self.slots[index])
+    }
+
+    fn push_omap_slot(&mut self) -> (usize, *mut rados_omap_iter_t, *mut u8, *mut libc::c_int) {
+        match self.push_slot(Slot::Omap {
+            iter: ptr::null_mut(),
+            more: 0,
+            prval: 0,
+        }) {
+            (
+                index,
+                &mut Slot::Omap {
+                    ref mut iter,
+                    ref mut more,
+                    ref mut prval,
+                },
+            ) => (index, iter as *mut _, more as *mut _, prval as *mut _),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Read up to `len` bytes from the object, starting at `offset`, using
+    /// `rados_read_op_read`.
+    pub fn read(&mut self, offset: u64, len: usize) -> Output<Vec<u8>> {
+        let handle = self.handle;
+
+        let index = match self.push_slot(Slot::Read {
+            buf: vec![0u8; len],
+            bytes_read: 0,
+            prval: 0,
+        }) {
+            (
+                index,
+                &mut Slot::Read {
+                    ref mut buf,
+                    ref mut bytes_read,
+                    ref mut prval,
+                },
+            ) => {
+                unsafe {
+                    rados::rados_read_op_read(
+                        handle,
+                        offset,
+                        len,
+                        buf.as_mut_ptr() as *mut libc::c_char,
+                        bytes_read,
+                        prval,
+                    );
+                }
+
+                index
+            }
+            _ => unreachable!(),
+        };
+
+        Output::new(self.id, index, take_read)
+    }
+
+    /// Fetch the size and modification time of the object, using `rados_read_op_stat`.
+    pub fn stat(&mut self) -> Output<Stat> {
+        let handle = self.handle;
+
+        let index = match self.push_slot(Slot::Stat {
+            size: 0,
+            mtime: 0,
+            prval: 0,
+            version: 0,
+        }) {
+            (
+                index,
+                &mut Slot::Stat {
+                    ref mut size,
+                    ref mut mtime,
+                    ref mut prval,
+                    ..
+                },
+            ) => {
+                unsafe {
+                    rados::rados_read_op_stat(handle, size, mtime, prval);
+                }
+
+                index
+            }
+            _ => unreachable!(),
+        };
+
+        Output::new(self.id, index, take_stat)
+    }
+
+    /// Fetch the size and modification time of the object, with the modification time to the
+    /// nanosecond, using `rados_read_op_stat2`.
+    pub fn stat2(&mut self) -> Output<Stat2> {
+        let handle = self.handle;
+
+        let index = match self.push_slot(Slot::Stat2 {
+            size: 0,
+            mtime: ffi::timespec::default(),
+            prval: 0,
+            version: 0,
+        }) {
+            (
+                index,
+                &mut Slot::Stat2 {
+                    ref mut size,
+                    ref mut mtime,
+                    ref mut prval,
+                    ..
+                },
+            ) => {
+                unsafe {
+                    ffi::rados_read_op_stat2(handle, size, mtime, prval);
+                }
+
+                index
+            }
+            _ => unreachable!(),
+        };
+
+        Output::new(self.id, index, take_stat2)
+    }
+
+    /// Fetch every extended attribute of the object, using `rados_read_op_getxattrs`.
+    pub fn xattrs(&mut self) -> Output<BTreeMap<String, Vec<u8>>> {
+        let handle = self.handle;
+
+        let index = match self.push_slot(Slot::Xattrs {
+            iter: ptr::null_mut(),
+            prval: 0,
+        }) {
+            (
+                index,
+                &mut Slot::Xattrs {
+                    ref mut iter,
+                    ref mut prval,
+                },
+            ) => {
+                unsafe {
+                    rados::rados_read_op_getxattrs(handle, iter, prval);
+                }
+
+                index
+            }
+            _ => unreachable!(),
+        };
+
+        Output::new(self.id, index, take_xattrs)
+    }
+
+    /// Guard the operation on the object existing, using `rados_read_op_assert_exists`. If it
+    /// does not, the operation fails with `ENOENT`.
+    pub fn assert_exists(&mut self) -> &mut ReadOp {
+        unsafe {
+            rados::rados_read_op_assert_exists(self.handle);
+        }
+
+        self
+    }
+
+    /// Guard the operation on the object's version being exactly `version`, using
+    /// `rados_read_op_assert_version`. If the object is newer (`version` is too low), the
+    /// operation fails with `ERANGE`; if it is older (`version` is too high), with `EOVERFLOW`.
+    pub fn assert_version(&mut self, version: u64) -> &mut ReadOp {
+        unsafe {
+            rados::rados_read_op_assert_version(self.handle, version);
+        }
+
+        self
+    }
+
+    /// Guard the operation on a comparison between the value of one of the object's extended
+    /// attributes and a given value, using `rados_read_op_cmpxattr`. If the comparison does not
+    /// hold, the operation fails with `ErrorKind::ComparisonFailed`.
+    pub fn cmp_xattr(&mut self, key: &str, op: CmpOp, value: &[u8]) -> Result<&mut ReadOp> {
+        let key_cstr = POOL.get_str(key)?;
+
+        unsafe {
+            rados::rados_read_op_cmpxattr(
+                self.handle,
+                key_cstr.as_ptr(),
+                op.as_raw(),
+                value.as_ptr() as *const libc::c_char,
+                value.len(),
+            );
+        }
+
+        mem::drop(key_cstr);
+
+        Ok(self)
+    }
+
+    /// Guard the operation on the object's data at `offset` matching `buf`, using
+    /// `rados_read_op_cmpext`. If it does not, the operation fails with
+    /// `ErrorKind::ExtentMismatch`, carrying the offset of the first mismatched byte relative to
+    /// `offset`.
+    pub fn cmp_ext(&mut self, buf: &[u8], offset: u64) -> &mut ReadOp {
+        unsafe {
+            ffi::rados_read_op_cmpext(
+                self.handle,
+                buf.as_ptr() as *const libc::c_char,
+                buf.len(),
+                offset,
+                ptr::null_mut(),
+            );
+        }
+
+        self.cmpext = true;
+
+        self
+    }
+
+    /// Guard the operation on a comparison between the value of a key in the object's omap and a
+    /// given value, using `rados_read_op_omap_cmp`. If the comparison does not hold, the
+    /// operation fails with `ErrorKind::ComparisonFailed`. Only `CmpOp::Eq`, `CmpOp::Lt` and
+    /// `CmpOp::Gt` are supported for omap comparisons; any other operator is an error.
+    pub fn omap_cmp(&mut self, key: &str, op: CmpOp, value: &[u8]) -> Result<&mut ReadOp> {
+        let op = op.as_omap_raw()?;
+        let key_cstr = POOL.get_str(key)?;
+
+        unsafe {
+            rados::rados_read_op_omap_cmp(
+                self.handle,
+                key_cstr.as_ptr(),
+                op,
+                value.as_ptr() as *const libc::c_char,
+                value.len(),
+                ptr::null_mut(),
+            );
+        }
+
+        mem::drop(key_cstr);
+
+        Ok(self)
+    }
+
+    /// Fetch up to `max` key/value pairs from the object's omap, starting after the key
+    /// `start_after` and only including keys beginning with `prefix`, using
+    /// `rados_read_op_omap_get_vals2`. Empty strings may be passed to start from the beginning
+    /// of the omap and to disable prefix filtering.
+    pub fn omap_vals(
+        &mut self,
+        start_after: &str,
+        prefix: &str,
+        max: u64,
+    ) -> Result<Output<OmapPage<(String, Vec<u8>)>>> {
+        let start_after_cstr = POOL.get_str(start_after)?;
+        let prefix_cstr = POOL.get_str(prefix)?;
+        let (index, iter, more, prval) = self.push_omap_slot();
+
+        unsafe {
+            ffi::rados_read_op_omap_get_vals2(
+                self.handle,
+                start_after_cstr.as_ptr(),
+                prefix_cstr.as_ptr(),
+                max,
+                iter,
+                more,
+                prval,
+            );
+        }
+
+        mem::drop(start_after_cstr);
+        mem::drop(prefix_cstr);
+
+        Ok(Output::new(self.id, index, take_omap_vals))
+    }
+
+    /// Fetch up to `max` keys from the object's omap, starting after the key `start_after`,
+    /// using `rados_read_op_omap_get_keys2`.
+    pub fn omap_keys(&mut self, start_after: &str, max: u64) -> Result<Output<OmapPage<String>>> {
+        let start_after_cstr = POOL.get_str(start_after)?;
+        let (index, iter, more, prval) = self.push_omap_slot();
+
+        unsafe {
+            ffi::rados_read_op_omap_get_keys2(
+                self.handle,
+                start_after_cstr.as_ptr(),
+                max,
+                iter,
+                more,
+                prval,
+            );
+        }
+
+        mem::drop(start_after_cstr);
+
+        Ok(Output::new(self.id, index, take_omap_keys))
+    }
+
+    /// Fetch the values of specific keys from the object's omap, using
+    /// `rados_read_op_omap_get_vals_by_keys`. Keys which are not present are left out of the
+    /// result.
+    pub fn omap_vals_by_keys<I, K>(&mut self, keys: I) -> Result<Output<BTreeMap<String, Vec<u8>>>>
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<str>,
+    {
+        let keys = key_cstrings(keys)?;
+        let key_ptrs = keys.iter().map(|key| key.as_ptr()).collect::<Vec<_>>();
+        let (index, iter, _, prval) = self.push_omap_slot();
+
+        unsafe {
+            rados::rados_read_op_omap_get_vals_by_keys(
+                self.handle,
+                key_ptrs.as_ptr(),
+                keys.len(),
+                iter,
+                prval,
+            );
+        }
+
+        Ok(Output::new(self.id, index, take_omap_map))
+    }
+}
+
+impl Default for ReadOp {
+    fn default() -> ReadOp {
+        ReadOp::new()
+    }
+}
+
+/// A `ReadOp` which has completed, from which the results of its steps may be taken.
+#[derive(Debug)]
+pub struct CompletedReadOp {
+    op: ReadOp,
+    version: u64,
+}
+
+impl CompletedReadOp {
+    fn new(mut op: ReadOp, version: u64) -> CompletedReadOp {
+        for slot in &mut op.slots {
+            match **slot {
+                Slot::Stat {
+                    version: ref mut slot_version,
+                    ..
+                }
+                | Slot::Stat2 {
+                    version: ref mut slot_version,
+                    ..
+                } => *slot_version = version,
+                _ => {}
+            }
+        }
+
+        CompletedReadOp { op, version }
+    }
+
+    /// The version of the object as of this read.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Take the result of a step of the operation. If the step itself failed, its error is
+    /// returned here.
+    pub fn take<T>(&mut self, output: Output<T>) -> Result<T> {
+        if output.op_id != self.op.id {
+            bail!("output handle does not belong to this read operation");
+        }
+
+        match self.op.slots.get_mut(output.index) {
+            Some(slot) => (output.take)(slot),
+            None => bail!("output handle does not belong to this read operation"),
+        }
+    }
+}
+
+/// The type of a compound write operation which has yet to complete. The operation is kept
+/// alive until librados is finished with it.
+#[derive(Debug)]
+pub struct WriteOpFuture {
+    data_future: DataFuture<WriteOp>,
+    map_err: fn(Error) -> Error,
+}
+
+impl WriteOpFuture {
+    pub(super) fn failed(error: Error) -> WriteOpFuture {
+        WriteOpFuture {
+            data_future: DataFuture::failed(error),
+            map_err: errors::comparison,
+        }
+    }
+
+    /// Turn this future into one which yields the version of the object after the operation,
+    /// using `rados_aio_get_version`.
+    pub fn versioned(self) -> VersionFuture<WriteOp> {
+        VersionFuture {
+            completion_res: self.data_future.completion_res,
+            map_err: self.map_err,
+        }
+    }
+}
+
+impl RadosFuture for WriteOpFuture {
+    type Item = ();
+
+    fn poll_rados(&mut self, wake: &Wake) -> Poll<Result<Self::Item>> {
+        self.data_future
+            .poll_rados(wake)
+            .map(|res| res.map(|_| ()).map_err(self.map_err))
+    }
+}
+
+rados_future!([] WriteOpFuture);
+
+/// The type of a compound read operation which has yet to complete.
+#[derive(Debug)]
+pub struct ReadOpFuture {
+    data_future: DataFuture<ReadOp>,
+    map_err: fn(Error) -> Error,
+}
+
+impl RadosFuture for ReadOpFuture {
+    type Item = CompletedReadOp;
+
+    fn poll_rados(&mut self, wake: &Wake) -> Poll<Result<Self::Item>> {
+        let map_err = self.map_err;
+
+        self.data_future.poll_return(wake).map(|res| {
+            res.map(|ret| CompletedReadOp::new(ret.data, ret.version))
+                .map_err(map_err)
+        })
+    }
+}
+
+rados_future!([] ReadOpFuture);
+
+/// A future yielding the result of a single step of a compound read operation.
+#[derive(Debug)]
+pub struct OutputFuture<T> {
+    read_op_future: ReadOpFuture,
+    output: Option<Output<T>>,
+}
+
+impl<T> OutputFuture<T> {
+    fn new(read_op_future: ReadOpFuture, output: Output<T>) -> OutputFuture<T> {
+        OutputFuture {
+            read_op_future,
+            output: Some(output),
+        }
+    }
+
+    fn failed(error: Error) -> OutputFuture<T> {
+        OutputFuture {
+            read_op_future: ReadOpFuture {
+                data_future: DataFuture::failed(error),
+                map_err: errors::comparison,
+            },
+            output: None,
+        }
+    }
+}
+
+impl<T> RadosFuture for OutputFuture<T> {
+    type Item = T;
+
+    fn poll_rados(&mut self, wake: &Wake) -> Poll<Result<Self::Item>> {
+        let mut completed = try_ready!(self.read_op_future.poll_rados(wake));
+
+        Poll::Ready(completed.take(self.output.take().unwrap()))
+    }
+}
+
+rados_future!([T] OutputFuture<T>);
+
+/// The metadata of an object, fetched in a single round trip by `Context::stat_extended`.
+///
+/// The librados C API provides no way to read an object's omap header, so its size cannot be
+/// included here.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtendedStat {
+    pub size: u64,
+
+    /// The modification time of the object, to the nanosecond.
+    pub last_modified: DateTime<Utc>,
+
+    /// The version of the object, which is incremented by every write to it.
+    pub version: u64,
+
+    /// The names of the object's extended attributes, in ascending order, if they were asked
+    /// for.
+    pub xattr_names: Option<Vec<String>>,
+}
+
+/// The steps of the `ReadOp` behind an extended stat.
+#[derive(Debug)]
+struct ExtendedStatOutputs {
+    stat: Output<Stat2>,
+    xattrs: Option<Output<BTreeMap<String, Vec<u8>>>>,
+}
+
+impl ExtendedStatOutputs {
+    fn new(op: &mut ReadOp, xattr_names: bool) -> ExtendedStatOutputs {
+        ExtendedStatOutputs {
+            stat: op.stat2(),
+            xattrs: if xattr_names { Some(op.xattrs()) } else { None },
+        }
+    }
+
+    fn take(self, completed: &mut CompletedReadOp) -> Result<ExtendedStat> {
+        let stat = completed.take(self.stat)?;
+
+        let xattr_names = match self.xattrs {
+            Some(xattrs) => Some(
+                completed
+                    .take(xattrs)?
+                    .into_iter()
+                    .map(|(name, _)| name)
+                    .collect(),
+            ),
+            None => None,
+        };
+
+        Ok(ExtendedStat {
+            size: stat.size,
+            last_modified: stat.last_modified,
+            version: stat.version,
+            xattr_names,
+        })
+    }
+}
+
+/// The type of an extended stat which has yet to complete.
+#[derive(Debug)]
+pub struct ExtendedStatFuture {
+    read_op_future: ReadOpFuture,
+    outputs: Option<ExtendedStatOutputs>,
+}
+
+impl RadosFuture for ExtendedStatFuture {
+    type Item = ExtendedStat;
+
+    fn poll_rados(&mut self, wake: &Wake) -> Poll<Result<Self::Item>> {
+        let mut completed = try_ready!(self.read_op_future.poll_rados(wake));
+
+        Poll::Ready(self.outputs.take().unwrap().take(&mut completed))
+    }
+}
+
+rados_future!([] ExtendedStatFuture);
+
+impl Context {
+    /// Atomically execute a compound write operation on an object, using
+    /// `rados_write_op_operate`. The resulting version of the object is available afterwards
+    /// from `Context::last_version`.
+    pub fn operate(&mut self, obj: &str, op: WriteOp) -> Result<()> {
+        let object_id = POOL.get_str(obj)?;
+
+        errors::librados(unsafe {
+            rados::rados_write_op_operate(
+                op.handle,
+                self.handle,
+                object_id.as_ptr(),
+                ptr::null_mut(),
+                0,
+            )
+        }).map_err(op.map_err())?;
+
+        mem::drop(object_id);
+
+        Ok(())
+    }
+
+    /// Asynchronously and atomically execute a compound write operation on an object, using
+    /// `rados_aio_write_op_operate`.
+    pub fn operate_async(&mut self, obj: &str, op: WriteOp) -> WriteOpFuture {
+        let op_handle = op.handle;
+        let map_err = op.map_err();
+
+        let data_future = DataFuture::new(op, |completion_handle| {
+            let object_id = POOL.get_str(obj)?;
+
+            errors::librados(unsafe {
+                rados::rados_aio_write_op_operate(
+                    op_handle,
+                    self.handle,
+                    completion_handle,
+                    object_id.as_ptr(),
+                    ptr::null_mut(),
+                    0,
+                )
+            })?;
+
+            mem::drop(object_id);
+
+            Ok(())
+        });
+
+        WriteOpFuture {
+            data_future,
+            map_err,
+        }
+    }
+
+    /// Execute a compound read operation on an object, using `rados_read_op_operate`.
+    pub fn read_op(&mut self, obj: &str, op: ReadOp) -> Result<CompletedReadOp> {
+        let object_id = POOL.get_str(obj)?;
+
+        errors::librados(unsafe {
+            rados::rados_read_op_operate(op.handle, self.handle, object_id.as_ptr(), 0)
+        }).map_err(op.map_err())?;
+
+        mem::drop(object_id);
+
+        Ok(CompletedReadOp::new(op, self.last_version()))
+    }
+
+    /// Fetch the size, nanosecond modification time and version of an object, and optionally
+    /// the names of its extended attributes, in a single compound read. Note that librados
+    /// cannot list extended attribute names alone, so asking for them also fetches their values.
+    pub fn stat_extended(&mut self, obj: &str, xattr_names: bool) -> Result<ExtendedStat> {
+        let mut op = ReadOp::new();
+        let outputs = ExtendedStatOutputs::new(&mut op, xattr_names);
+
+        outputs.take(&mut self.read_op(obj, op)?)
+    }
+
+    /// Asynchronously fetch the metadata of an object in a single compound read, as with
+    /// `Context::stat_extended`.
+    pub fn stat_extended_async(&mut self, obj: &str, xattr_names: bool) -> ExtendedStatFuture {
+        let mut op = ReadOp::new();
+        let outputs = ExtendedStatOutputs::new(&mut op, xattr_names);
+
+        ExtendedStatFuture {
+            read_op_future: self.read_op_async(obj, op),
+            outputs: Some(outputs),
+        }
+    }
+
+    /// Compare the data of an object at `offset` against `buf`, using `rados_cmpext`. A mismatch
+    /// is reported as `ErrorKind::ExtentMismatch`, carrying the offset of the first mismatched
+    /// byte relative to `offset`.
+    pub fn cmp_ext(&mut self, obj: &str, buf: &[u8], offset: u64) -> Result<()> {
+        let object_id = POOL.get_str(obj)?;
+
+        errors::librados(unsafe {
+            ffi::rados_cmpext(
+                self.handle,
+                object_id.as_ptr(),
+                buf.as_ptr() as *const libc::c_char,
+                buf.len(),
+                offset,
+            )
+        }).map_err(errors::cmpext)?;
+
+        mem::drop(object_id);
+
+        Ok(())
+    }
+
+    /// Asynchronously compare the data of an object at `offset` against `buf`, using
+    /// `rados_aio_cmpext`.
+    pub fn cmp_ext_async(&mut self, obj: &str, buf: &[u8], offset: u64) -> UnitFuture {
+        UnitFuture::with_map_err(errors::cmpext, |completion_handle| {
+            let object_id = POOL.get_str(obj)?;
+
+            errors::librados(unsafe {
+                ffi::rados_aio_cmpext(
+                    self.handle,
+                    object_id.as_ptr(),
+                    completion_handle,
+                    buf.as_ptr() as *const libc::c_char,
+                    buf.len(),
+                    offset,
+                )
+            })?;
+
+            mem::drop(object_id);
+
+            Ok(())
+        })
+    }
+
+    /// Compare the value of one of an object's extended attributes against `value`. If the
+    /// comparison does not hold, `ErrorKind::ComparisonFailed` is returned.
+    pub fn cmp_xattr(&mut self, obj: &str, key: &str, op: CmpOp, value: &[u8]) -> Result<()> {
+        let mut read_op = ReadOp::new();
+        read_op.cmp_xattr(key, op, value)?;
+        self.read_op(obj, read_op).map(|_| ())
+    }
+
+    /// Compare the value of a key in an object's omap against `value`. If the comparison does
+    /// not hold, `ErrorKind::ComparisonFailed` is returned. As with `ReadOp::omap_cmp`, only
+    /// `CmpOp::Eq`, `CmpOp::Lt` and `CmpOp::Gt` are supported.
+    pub fn omap_cmp(&mut self, obj: &str, key: &str, op: CmpOp, value: &[u8]) -> Result<()> {
+        let mut read_op = ReadOp::new();
+        read_op.omap_cmp(key, op, value)?;
+        self.read_op(obj, read_op).map(|_| ())
+    }
+
+    /// Build a single-step read operation with `build`, execute it, and take the result of the
+    /// step.
+    pub(super) fn read_output<T, F>(&mut self, obj: &str, build: F) -> Result<T>
+    where
+        F: FnOnce(&mut ReadOp) -> Result<Output<T>>,
+    {
+        let mut op = ReadOp::new();
+        let output = build(&mut op)?;
+        self.read_op(obj, op)?.take(output)
+    }
+
+    /// Asynchronously execute a single-step read operation built with `build`, yielding the
+    /// result of the step.
+    pub(super) fn read_output_async<T, F>(&mut self, obj: &str, build: F) -> OutputFuture<T>
+    where
+        F: FnOnce(&mut ReadOp) -> Result<Output<T>>,
+    {
+        let mut op = ReadOp::new();
+
+        match build(&mut op) {
+            Ok(output) => OutputFuture::new(self.read_op_async(obj, op), output),
+            Err(error) => OutputFuture::failed(error),
+        }
+    }
+
+    /// Asynchronously execute a compound read operation on an object, using
+    /// `rados_aio_read_op_operate`.
+    pub fn read_op_async(&mut self, obj: &str, op: ReadOp) -> ReadOpFuture {
+        let op_handle = op.handle;
+        let map_err = op.map_err();
+
+        let data_future = DataFuture::new(op, |completion_handle| {
+            let object_id = POOL.get_str(obj)?;
+
+            errors::librados(unsafe {
+                rados::rados_aio_read_op_operate(
+                    op_handle,
+                    self.handle,
+                    completion_handle,
+                    object_id.as_ptr(),
+                    0,
+                )
+            })?;
+
+            mem::drop(object_id);
+
+            Ok(())
+        });
+
+        ReadOpFuture {
+            data_future,
+            map_err,
+        }
+    }
+}
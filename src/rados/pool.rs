@@ -0,0 +1,190 @@
+//! Creating, deleting, and looking up pools, and pool statistics.
+
+use std::mem;
+use std::str;
+
+use ceph::rados;
+use libc;
+
+use errors::{self, Result};
+use ffi;
+
+use super::{get_string, Connection, Context, POOL};
+
+/// Usage statistics for a single pool, as returned by `Context::pool_stat`. As with
+/// `ClusterStat`, sizes in "kb" are measured in kibibytes.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PoolStat {
+    /// The space used by the pool, in bytes.
+    pub num_bytes: u64,
+
+    /// The space used by the pool, in kibibytes.
+    pub num_kb: u64,
+
+    /// The number of objects in the pool.
+    pub num_objects: u64,
+
+    /// The number of snapshot clones of objects in the pool.
+    pub num_object_clones: u64,
+
+    /// The number of object replicas stored for the pool, which is `num_objects` times the
+    /// replication factor when the pool is healthy.
+    pub num_object_copies: u64,
+
+    pub num_objects_missing_on_primary: u64,
+    pub num_objects_unfound: u64,
+    pub num_objects_degraded: u64,
+
+    /// The number of read operations performed on the pool.
+    pub num_rd: u64,
+
+    /// The amount of data read from the pool, in kibibytes.
+    pub num_rd_kb: u64,
+
+    /// The number of write operations performed on the pool.
+    pub num_wr: u64,
+
+    /// The amount of data written to the pool, in kibibytes.
+    pub num_wr_kb: u64,
+
+    /// The amount of data stored in the pool by clients, in bytes, before replication.
+    pub num_user_bytes: u64,
+
+    /// The amount of data in the pool which was compressed, in bytes, before compression.
+    pub compressed_bytes_orig: u64,
+
+    /// The amount of data in the pool which was compressed, in bytes, after compression.
+    pub compressed_bytes: u64,
+
+    /// The space allocated on disk for compressed data in the pool, in bytes.
+    pub compressed_bytes_alloc: u64,
+}
+
+impl Connection {
+    /// Create a pool with the given name, using `rados_pool_create`. The pool is created with
+    /// the cluster's default settings and CRUSH rule.
+    pub fn create_pool(&mut self, name: &str) -> Result<()> {
+        let name_cstr = POOL.get_str(name)?;
+
+        errors::librados(unsafe {
+            rados::rados_pool_create(self.conn.handle, name_cstr.as_ptr())
+        })?;
+
+        mem::drop(name_cstr);
+
+        Ok(())
+    }
+
+    /// Create a pool with the given name, placed according to the given CRUSH rule, using
+    /// `rados_pool_create_with_crush_rule`.
+    pub fn create_pool_with_crush_rule(&mut self, name: &str, crush_rule: u8) -> Result<()> {
+        let name_cstr = POOL.get_str(name)?;
+
+        errors::librados(unsafe {
+            rados::rados_pool_create_with_crush_rule(
+                self.conn.handle,
+                name_cstr.as_ptr(),
+                crush_rule,
+            )
+        })?;
+
+        mem::drop(name_cstr);
+
+        Ok(())
+    }
+
+    /// Delete a pool and every object in it, using `rados_pool_delete`. Note that the monitors
+    /// refuse to delete pools unless `mon_allow_pool_delete` is set.
+    pub fn delete_pool(&mut self, name: &str) -> Result<()> {
+        let name_cstr = POOL.get_str(name)?;
+
+        errors::librados(unsafe {
+            rados::rados_pool_delete(self.conn.handle, name_cstr.as_ptr())
+        })?;
+
+        mem::drop(name_cstr);
+
+        Ok(())
+    }
+
+    /// List the names of every pool in the cluster, using `rados_pool_list`.
+    pub fn list_pools(&mut self) -> Result<Vec<String>> {
+        let mut buf = vec![0u8; 256];
+
+        // `rados_pool_list` returns the length of buffer it needs, filling in as much as fits.
+        loop {
+            let len = errors::librados_res(unsafe {
+                rados::rados_pool_list(
+                    self.conn.handle,
+                    buf.as_mut_ptr() as *mut libc::c_char,
+                    buf.len(),
+                )
+            })? as usize;
+
+            if len <= buf.len() {
+                buf.truncate(len);
+                break;
+            }
+
+            buf = vec![0u8; len];
+        }
+
+        // The buffer holds one NUL-terminated name after another, followed by an extra NUL.
+        buf.split(|&b| b == 0)
+            .take_while(|name| !name.is_empty())
+            .map(|name| Ok(str::from_utf8(name)?.to_owned()))
+            .collect()
+    }
+
+    /// Look up the ID of the pool with the given name, using `rados_pool_lookup`.
+    pub fn pool_lookup(&mut self, name: &str) -> Result<u64> {
+        let name_cstr = POOL.get_str(name)?;
+
+        let id = unsafe { rados::rados_pool_lookup(self.conn.handle, name_cstr.as_ptr()) };
+
+        mem::drop(name_cstr);
+
+        if id < 0 {
+            errors::librados(id as i32)?;
+        }
+
+        Ok(id as u64)
+    }
+
+    /// Look up the name of the pool with the given ID, using `rados_pool_reverse_lookup`.
+    pub fn pool_reverse_lookup(&mut self, id: u64) -> Result<String> {
+        let handle = self.conn.handle;
+
+        get_string(|buf, len| unsafe {
+            rados::rados_pool_reverse_lookup(handle, id as i64, buf, len)
+        })
+    }
+}
+
+impl Context {
+    /// Fetch the usage statistics of this context's pool, using `rados_ioctx_pool_stat`.
+    pub fn pool_stat(&mut self) -> Result<PoolStat> {
+        let mut pool_stat: ffi::rados_pool_stat_t = unsafe { mem::zeroed() };
+
+        errors::librados(unsafe { ffi::rados_ioctx_pool_stat(self.handle, &mut pool_stat) })?;
+
+        Ok(PoolStat {
+            num_bytes: pool_stat.num_bytes,
+            num_kb: pool_stat.num_kb,
+            num_objects: pool_stat.num_objects,
+            num_object_clones: pool_stat.num_object_clones,
+            num_object_copies: pool_stat.num_object_copies,
+            num_objects_missing_on_primary: pool_stat.num_objects_missing_on_primary,
+            num_objects_unfound: pool_stat.num_objects_unfound,
+            num_objects_degraded: pool_stat.num_objects_degraded,
+            num_rd: pool_stat.num_rd,
+            num_rd_kb: pool_stat.num_rd_kb,
+            num_wr: pool_stat.num_wr,
+            num_wr_kb: pool_stat.num_wr_kb,
+            num_user_bytes: pool_stat.num_user_bytes,
+            compressed_bytes_orig: pool_stat.compressed_bytes_orig,
+            compressed_bytes: pool_stat.compressed_bytes,
+            compressed_bytes_alloc: pool_stat.compressed_bytes_alloc,
+        })
+    }
+}
@@ -0,0 +1,304 @@
+//! Pool snapshots and self-managed snapshots.
+//!
+//! A pool snapshot captures every object in a pool at once; objects can then be read as of the
+//! snapshot, or rolled back to it individually. Self-managed snapshots are instead allocated by
+//! the client, which is responsible for telling librados which snapshots each write belongs to
+//! through a `SnapContext`, as RBD does for its images.
+//!
+//! Note that pool snapshots and self-managed snapshots are mutually exclusive: a pool which has
+//! had self-managed snapshots taken on it cannot have pool snapshots, and vice versa.
+
+use std::mem;
+use std::task::Poll;
+
+use ceph::rados;
+use chrono::{DateTime, Local, TimeZone};
+use libc;
+
+use async::{RadosFuture, Wake};
+use errors::{self, Result};
+use ffi;
+
+use super::{get_string, Context, DataFuture, UnitFuture, POOL};
+
+/// The snapshot ID which denotes the current, writable state of an object rather than any
+/// snapshot of it. This is `LIBRADOS_SNAP_HEAD` in `librados.h`.
+pub const SNAP_HEAD: u64 = !1;
+
+/// A pool snapshot, as listed by `Context::snap_list`.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PoolSnapshot {
+    pub id: u64,
+    pub name: String,
+    pub created: DateTime<Local>,
+}
+
+/// The snapshot context attached to writes when using self-managed snapshots: the most recent
+/// snapshot ID, and every existing snapshot ID in descending order.
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct SnapContext {
+    pub seq: u64,
+    pub snaps: Vec<u64>,
+}
+
+impl SnapContext {
+    /// An empty snapshot context, for objects which have no snapshots.
+    pub fn new() -> SnapContext {
+        SnapContext::default()
+    }
+
+    /// Add a newly created snapshot, which must be newer than every snapshot already in the
+    /// context.
+    pub fn push(&mut self, snap: u64) {
+        self.seq = snap;
+        self.snaps.insert(0, snap);
+    }
+
+    /// Remove a snapshot from the context, for example after it has been removed with
+    /// `Context::selfmanaged_snap_remove`.
+    pub fn remove(&mut self, snap: u64) {
+        self.snaps.retain(|&s| s != snap);
+    }
+}
+
+/// A future yielding the ID of a newly created self-managed snapshot.
+#[derive(Debug)]
+pub struct SnapIdFuture {
+    data_future: DataFuture<Box<u64>>,
+}
+
+impl RadosFuture for SnapIdFuture {
+    type Item = u64;
+
+    fn poll_rados(&mut self, wake: &Wake) -> Poll<Result<Self::Item>> {
+        self.data_future
+            .poll_rados(wake)
+            .map(|res| res.map(|boxed| *boxed))
+    }
+}
+
+rados_future!([] SnapIdFuture);
+
+impl Context {
+    /// Create a snapshot of the whole pool with the given name, using `rados_ioctx_snap_create`.
+    pub fn snap_create(&mut self, name: &str) -> Result<()> {
+        let name_cstr = POOL.get_str(name)?;
+
+        errors::librados(unsafe {
+            rados::rados_ioctx_snap_create(self.handle, name_cstr.as_ptr())
+        })?;
+
+        mem::drop(name_cstr);
+
+        Ok(())
+    }
+
+    /// Remove the pool snapshot with the given name, using `rados_ioctx_snap_remove`.
+    pub fn snap_remove(&mut self, name: &str) -> Result<()> {
+        let name_cstr = POOL.get_str(name)?;
+
+        errors::librados(unsafe {
+            rados::rados_ioctx_snap_remove(self.handle, name_cstr.as_ptr())
+        })?;
+
+        mem::drop(name_cstr);
+
+        Ok(())
+    }
+
+    /// Look up the ID of the pool snapshot with the given name, using `rados_ioctx_snap_lookup`.
+    pub fn snap_lookup(&mut self, name: &str) -> Result<u64> {
+        let name_cstr = POOL.get_str(name)?;
+        let mut id = 0;
+
+        errors::librados(unsafe {
+            rados::rados_ioctx_snap_lookup(self.handle, name_cstr.as_ptr(), &mut id)
+        })?;
+
+        mem::drop(name_cstr);
+
+        Ok(id)
+    }
+
+    /// Get the name of the pool snapshot with the given ID, using `rados_ioctx_snap_get_name`.
+    pub fn snap_name(&mut self, id: u64) -> Result<String> {
+        let handle = self.handle;
+
+        get_string(|buf, len| unsafe {
+            rados::rados_ioctx_snap_get_name(handle, id, buf, len as libc::c_int)
+        })
+    }
+
+    /// Get the time at which the pool snapshot with the given ID was taken, using
+    /// `rados_ioctx_snap_get_stamp`.
+    pub fn snap_stamp(&mut self, id: u64) -> Result<DateTime<Local>> {
+        let mut time = 0;
+
+        errors::librados(unsafe { rados::rados_ioctx_snap_get_stamp(self.handle, id, &mut time) })?;
+
+        Ok(Local.timestamp(time, 0))
+    }
+
+    /// List the IDs of every snapshot of the pool, using `rados_ioctx_snap_list`.
+    pub fn snap_list_ids(&mut self) -> Result<Vec<u64>> {
+        let mut ids = vec![0; 16];
+
+        loop {
+            let res = unsafe {
+                rados::rados_ioctx_snap_list(
+                    self.handle,
+                    ids.as_mut_ptr(),
+                    ids.len() as libc::c_int,
+                )
+            };
+
+            if res == -libc::ERANGE {
+                let len = ids.len();
+                ids.resize(len * 2, 0);
+            } else {
+                let count = errors::librados_res(res)? as usize;
+                ids.truncate(count);
+
+                return Ok(ids);
+            }
+        }
+    }
+
+    /// List every snapshot of the pool, along with its name and creation time.
+    pub fn snap_list(&mut self) -> Result<Vec<PoolSnapshot>> {
+        self.snap_list_ids()?
+            .into_iter()
+            .map(|id| {
+                Ok(PoolSnapshot {
+                    id,
+                    name: self.snap_name(id)?,
+                    created: self.snap_stamp(id)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Roll an object back to its state in the named pool snapshot, using
+    /// `rados_ioctx_snap_rollback`.
+    pub fn snap_rollback(&mut self, obj: &str, snap_name: &str) -> Result<()> {
+        let object_id = POOL.get_str(obj)?;
+        let snap_name_cstr = POOL.get_str(snap_name)?;
+
+        errors::librados(unsafe {
+            rados::rados_ioctx_snap_rollback(
+                self.handle,
+                object_id.as_ptr(),
+                snap_name_cstr.as_ptr(),
+            )
+        })?;
+
+        mem::drop((object_id, snap_name_cstr));
+
+        Ok(())
+    }
+
+    /// Make all further reads through this context, including `read` and `read_async`, read
+    /// objects as of the snapshot with the given ID, using `rados_ioctx_snap_set_read`. Passing
+    /// `None` goes back to reading the current state of objects.
+    pub fn set_read_snapshot(&mut self, snap: Option<u64>) {
+        unsafe {
+            rados::rados_ioctx_snap_set_read(self.handle, snap.unwrap_or(SNAP_HEAD));
+        }
+
+        self.read_snap = snap;
+    }
+
+    /// The snapshot this context reads from, if any.
+    pub fn read_snapshot(&self) -> Option<u64> {
+        self.read_snap
+    }
+
+    /// Allocate a new self-managed snapshot ID, using `rados_ioctx_selfmanaged_snap_create`. The
+    /// snapshot only takes effect once it is added to the `SnapContext` used for writes.
+    pub fn selfmanaged_snap_create(&mut self) -> Result<u64> {
+        let mut id = 0;
+
+        errors::librados(unsafe {
+            rados::rados_ioctx_selfmanaged_snap_create(self.handle, &mut id)
+        })?;
+
+        Ok(id)
+    }
+
+    /// Remove a self-managed snapshot, using `rados_ioctx_selfmanaged_snap_remove`.
+    pub fn selfmanaged_snap_remove(&mut self, snap: u64) -> Result<()> {
+        errors::librados(unsafe { rados::rados_ioctx_selfmanaged_snap_remove(self.handle, snap) })
+    }
+
+    /// Roll an object back to its state in a self-managed snapshot, using
+    /// `rados_ioctx_selfmanaged_snap_rollback`.
+    pub fn selfmanaged_snap_rollback(&mut self, obj: &str, snap: u64) -> Result<()> {
+        let object_id = POOL.get_str(obj)?;
+
+        errors::librados(unsafe {
+            rados::rados_ioctx_selfmanaged_snap_rollback(self.handle, object_id.as_ptr(), snap)
+        })?;
+
+        mem::drop(object_id);
+
+        Ok(())
+    }
+
+    /// Set the snapshot context attached to all further writes through this context, using
+    /// `rados_ioctx_selfmanaged_snap_set_write_ctx`. Handles opened from this context, such as
+    /// `ObjectHandle`s, are given the same snapshot context.
+    pub fn set_write_snap_context(&mut self, snapc: &SnapContext) -> Result<()> {
+        let mut snaps = snapc.snaps.clone();
+
+        errors::librados(unsafe {
+            rados::rados_ioctx_selfmanaged_snap_set_write_ctx(
+                self.handle,
+                snapc.seq,
+                snaps.as_mut_ptr(),
+                snaps.len() as libc::c_int,
+            )
+        })?;
+
+        self.write_snapc = Some(snapc.clone());
+
+        Ok(())
+    }
+
+    /// The snapshot context attached to writes through this context, if one has been set.
+    pub fn write_snap_context(&self) -> Option<&SnapContext> {
+        self.write_snapc.as_ref()
+    }
+
+    /// Asynchronously allocate a new self-managed snapshot ID, using
+    /// `rados_aio_ioctx_selfmanaged_snap_create`.
+    pub fn selfmanaged_snap_create_async(&mut self) -> SnapIdFuture {
+        let mut boxed = Box::new(0);
+        let id_ptr = &mut *boxed as *mut u64;
+
+        let data_future = DataFuture::new(boxed, |completion_handle| {
+            unsafe {
+                ffi::rados_aio_ioctx_selfmanaged_snap_create(
+                    self.handle,
+                    id_ptr,
+                    completion_handle,
+                );
+            }
+
+            Ok(())
+        });
+
+        SnapIdFuture { data_future }
+    }
+
+    /// Asynchronously remove a self-managed snapshot, using
+    /// `rados_aio_ioctx_selfmanaged_snap_remove`.
+    pub fn selfmanaged_snap_remove_async(&mut self, snap: u64) -> UnitFuture {
+        UnitFuture::new(|completion_handle| {
+            unsafe {
+                ffi::rados_aio_ioctx_selfmanaged_snap_remove(self.handle, snap, completion_handle);
+            }
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,473 @@
+//! Striping large logical objects over many RADOS objects, as `libradosstriper` does.
+//!
+//! A striped object is cut into stripe units, which are laid out round-robin over a set of
+//! `stripe_count` RADOS objects; once every object in the set holds `object_size` bytes, the next
+//! set of objects is started. The RADOS objects backing the striped object `name` are named
+//! `name.` followed by their index as sixteen hexadecimal digits, and the first of them also holds
+//! the layout and size of the striped object in extended attributes. Both follow the format used
+//! by `libradosstriper`.
+//!
+//! Striped objects are not locked: concurrent writers which extend the same striped object may
+//! race to update its size.
+
+use std::cmp;
+use std::collections::BTreeMap;
+use std::str;
+
+use libc;
+
+use async::block_on;
+use errors::{Error, ErrorKind, Result};
+
+use super::{Context, ReadOp, WriteOp};
+
+const XATTR_STRIPE_UNIT: &str = "striper.layout.stripe_unit";
+const XATTR_STRIPE_COUNT: &str = "striper.layout.stripe_count";
+const XATTR_OBJECT_SIZE: &str = "striper.layout.object_size";
+const XATTR_SIZE: &str = "striper.size";
+
+/// How a striped object is laid out over RADOS objects. All sizes are in bytes.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct StripeLayout {
+    /// The size of each piece of the striped object placed in a single RADOS object at a time.
+    pub stripe_unit: u64,
+
+    /// The number of RADOS objects each stripe is spread over.
+    pub stripe_count: u64,
+
+    /// The size of each RADOS object, which must be a multiple of `stripe_unit`.
+    pub object_size: u64,
+}
+
+/// The default layout, which is also that of `libradosstriper`: 4 MiB objects, each written whole
+/// before moving on to the next.
+impl Default for StripeLayout {
+    fn default() -> StripeLayout {
+        StripeLayout {
+            stripe_unit: 4 << 20,
+            stripe_count: 1,
+            object_size: 4 << 20,
+        }
+    }
+}
+
+/// A contiguous piece of a striped read or write, which falls within a single RADOS object.
+#[derive(Debug)]
+struct Extent {
+    object_no: u64,
+    object_offset: u64,
+    buf_offset: usize,
+    len: usize,
+}
+
+impl StripeLayout {
+    /// Create a layout, checking that it is valid.
+    pub fn new(stripe_unit: u64, stripe_count: u64, object_size: u64) -> Result<StripeLayout> {
+        let layout = StripeLayout {
+            stripe_unit,
+            stripe_count,
+            object_size,
+        };
+
+        layout.validate()?;
+
+        Ok(layout)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.stripe_unit == 0 || self.stripe_count == 0 || self.object_size == 0
+            || self.object_size % self.stripe_unit != 0
+        {
+            bail!(
+                "invalid stripe layout: stripe unit {}, stripe count {}, object size {}",
+                self.stripe_unit,
+                self.stripe_count,
+                self.object_size
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Split the range of `len` bytes at `offset` in the striped object into pieces within single
+    /// RADOS objects, in order of offset.
+    fn extents(&self, offset: u64, len: usize) -> Vec<Extent> {
+        let stripes_per_object = self.object_size / self.stripe_unit;
+        let mut extents: Vec<Extent> = Vec::new();
+        let mut done = 0;
+
+        while done < len {
+            let pos = offset + done as u64;
+            let block = pos / self.stripe_unit;
+            let stripe = block / self.stripe_count;
+
+            let object_no =
+                (stripe / stripes_per_object) * self.stripe_count + block % self.stripe_count;
+            let object_offset =
+                (stripe % stripes_per_object) * self.stripe_unit + pos % self.stripe_unit;
+            let n = cmp::min(
+                self.stripe_unit - pos % self.stripe_unit,
+                (len - done) as u64,
+            ) as usize;
+
+            // With a single object per stripe, consecutive stripe units are contiguous.
+            match extents.last_mut() {
+                Some(last)
+                    if last.object_no == object_no
+                        && last.object_offset + last.len as u64 == object_offset =>
+                {
+                    last.len += n;
+                }
+                _ => extents.push(Extent {
+                    object_no,
+                    object_offset,
+                    buf_offset: done,
+                    len: n,
+                }),
+            }
+
+            done += n;
+        }
+
+        extents
+    }
+
+    /// The amount of data held by the RADOS object with the given index, when the striped object
+    /// is `size` bytes long.
+    fn object_len(&self, size: u64, object_no: u64) -> u64 {
+        let set_bytes = self.object_size * self.stripe_count;
+        let set_start = (object_no / self.stripe_count) * set_bytes;
+
+        if size <= set_start {
+            return 0;
+        }
+
+        let rem = size - set_start;
+
+        if rem >= set_bytes {
+            return self.object_size;
+        }
+
+        let stripe_bytes = self.stripe_unit * self.stripe_count;
+        let partial = (rem % stripe_bytes)
+            .saturating_sub((object_no % self.stripe_count) * self.stripe_unit);
+
+        (rem / stripe_bytes) * self.stripe_unit + cmp::min(partial, self.stripe_unit)
+    }
+
+    /// The number of RADOS objects backing a striped object `size` bytes long. This is never
+    /// zero, as the first object holds the striped object's metadata.
+    fn object_count(&self, size: u64) -> u64 {
+        if size == 0 {
+            return 1;
+        }
+
+        let set_bytes = self.object_size * self.stripe_count;
+        let last_set = (size - 1) / set_bytes;
+        let rem = size - last_set * set_bytes;
+
+        let in_last_set = if rem >= self.stripe_unit * self.stripe_count {
+            self.stripe_count
+        } else {
+            (rem + self.stripe_unit - 1) / self.stripe_unit
+        };
+
+        last_set * self.stripe_count + in_last_set
+    }
+}
+
+/// The size and layout of a striped object, as returned by `Striper::stat`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StripedStat {
+    pub size: u64,
+    pub layout: StripeLayout,
+}
+
+/// Reads and writes striped objects in a pool, created by `Context::striper`.
+///
+/// Every operation on a striped object issues the operations on its RADOS objects asynchronously
+/// and all at once, and then waits for all of them to complete.
+#[derive(Debug)]
+pub struct Striper {
+    context: Context,
+    layout: StripeLayout,
+}
+
+fn not_found(error: &Error) -> bool {
+    match *error {
+        Error(ErrorKind::Rados(err), _) => err == libc::ENOENT as u32,
+        _ => false,
+    }
+}
+
+fn parse_xattr(xattrs: &BTreeMap<String, Vec<u8>>, soid: &str, key: &str) -> Result<u64> {
+    let value = match xattrs.get(key) {
+        Some(value) => str::from_utf8(value)?,
+        None => bail!("`{}` is not a striped object: it has no `{}` attribute", soid, key),
+    };
+
+    match value.trim_end_matches('\0').parse() {
+        Ok(n) => Ok(n),
+        Err(_) => bail!("striped object `{}` has an invalid `{}`: `{}`", soid, key, value),
+    }
+}
+
+impl Striper {
+    /// The layout used for striped objects created by this striper.
+    pub fn layout(&self) -> StripeLayout {
+        self.layout
+    }
+
+    /// Set the layout used for striped objects created by this striper. Existing striped objects
+    /// keep the layout they were created with.
+    pub fn set_layout(&mut self, layout: StripeLayout) -> Result<()> {
+        layout.validate()?;
+        self.layout = layout;
+
+        Ok(())
+    }
+
+    fn object_name(soid: &str, object_no: u64) -> String {
+        format!("{}.{:016x}", soid, object_no)
+    }
+
+    /// Get the size and layout of a striped object, from the extended attributes of its first
+    /// RADOS object. This fails with `ENOENT` if the striped object does not exist.
+    pub fn stat(&mut self, soid: &str) -> Result<StripedStat> {
+        let mut op = ReadOp::new();
+        let xattrs = op.xattrs();
+
+        let mut completed = self.context
+            .read_op(&Self::object_name(soid, 0), op)?;
+        let xattrs = completed.take(xattrs)?;
+
+        let layout = StripeLayout {
+            stripe_unit: parse_xattr(&xattrs, soid, XATTR_STRIPE_UNIT)?,
+            stripe_count: parse_xattr(&xattrs, soid, XATTR_STRIPE_COUNT)?,
+            object_size: parse_xattr(&xattrs, soid, XATTR_OBJECT_SIZE)?,
+        };
+
+        layout.validate()?;
+
+        Ok(StripedStat {
+            size: parse_xattr(&xattrs, soid, XATTR_SIZE)?,
+            layout,
+        })
+    }
+
+    /// Get the size and layout of a striped object, creating it empty with this striper's
+    /// layout if it does not exist.
+    fn stat_or_create(&mut self, soid: &str) -> Result<StripedStat> {
+        match self.stat(soid) {
+            Err(ref error) if not_found(error) => {}
+            res => return res,
+        }
+
+        let layout = self.layout;
+        let op = WriteOp::new()
+            .create(true)
+            .set_xattr(XATTR_STRIPE_UNIT, layout.stripe_unit.to_string().as_bytes())?
+            .set_xattr(XATTR_STRIPE_COUNT, layout.stripe_count.to_string().as_bytes())?
+            .set_xattr(XATTR_OBJECT_SIZE, layout.object_size.to_string().as_bytes())?
+            .set_xattr(XATTR_SIZE, b"0")?;
+
+        match self.context.operate(&Self::object_name(soid, 0), op) {
+            Ok(()) => Ok(StripedStat { size: 0, layout }),
+
+            // Somebody else created it first.
+            Err(Error(ErrorKind::Rados(err), _)) if err == libc::EEXIST as u32 => self.stat(soid),
+
+            Err(error) => Err(error),
+        }
+    }
+
+    fn set_size(&mut self, soid: &str, size: u64) -> Result<()> {
+        self.context.set_xattr(
+            &Self::object_name(soid, 0),
+            XATTR_SIZE,
+            size.to_string().as_bytes(),
+        )
+    }
+
+    /// Write a buffer to a striped object at the given offset, creating the striped object if it
+    /// does not exist.
+    pub fn write(&mut self, soid: &str, buf: &[u8], offset: u64) -> Result<()> {
+        let stat = self.stat_or_create(soid)?;
+
+        let futures = stat.layout
+            .extents(offset, buf.len())
+            .into_iter()
+            .map(|extent| {
+                let data = &buf[extent.buf_offset..extent.buf_offset + extent.len];
+
+                self.context.write_async(
+                    &Self::object_name(soid, extent.object_no),
+                    data,
+                    extent.object_offset,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // Wait for every write before reporting any failure.
+        let results = futures.into_iter().map(block_on).collect::<Vec<_>>();
+        results.into_iter().collect::<Result<Vec<()>>>()?;
+
+        let end = offset + buf.len() as u64;
+
+        if end > stat.size {
+            self.set_size(soid, end)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the entirety of a striped object, replacing any existing contents.
+    pub fn write_full(&mut self, soid: &str, buf: &[u8]) -> Result<()> {
+        self.stat_or_create(soid)?;
+        self.truncate(soid, 0)?;
+
+        self.write(soid, buf, 0)
+    }
+
+    /// Append a buffer to the end of a striped object, creating the striped object if it does not
+    /// exist.
+    pub fn append(&mut self, soid: &str, buf: &[u8]) -> Result<()> {
+        let stat = self.stat_or_create(soid)?;
+
+        self.write(soid, buf, stat.size)
+    }
+
+    /// Read from a striped object at the given offset into a buffer, returning the number of bytes
+    /// read. Parts of the striped object which were never written read as zeroes.
+    pub fn read(&mut self, soid: &str, buf: &mut [u8], offset: u64) -> Result<usize> {
+        let stat = self.stat(soid)?;
+
+        if offset >= stat.size {
+            return Ok(0);
+        }
+
+        let len = cmp::min(buf.len() as u64, stat.size - offset) as usize;
+
+        let futures = stat.layout
+            .extents(offset, len)
+            .into_iter()
+            .map(|extent| {
+                let future = self.context.read_async(
+                    &Self::object_name(soid, extent.object_no),
+                    vec![0u8; extent.len],
+                    extent.object_offset,
+                );
+
+                (extent, future)
+            })
+            .collect::<Vec<_>>();
+
+        let results = futures
+            .into_iter()
+            .map(|(extent, future)| (extent, block_on(future)))
+            .collect::<Vec<_>>();
+
+        for (extent, result) in results {
+            let target = &mut buf[extent.buf_offset..extent.buf_offset + extent.len];
+
+            // Short reads, and missing objects, are holes in the striped object.
+            let read = match result {
+                Ok((read, data)) => {
+                    let read = read as usize;
+                    target[..read].copy_from_slice(&data[..read]);
+                    read
+                }
+                Err(ref error) if not_found(error) => 0,
+                Err(error) => return Err(error),
+            };
+
+            for byte in &mut target[read..] {
+                *byte = 0;
+            }
+        }
+
+        Ok(len)
+    }
+
+    /// Resize a striped object, filling with zeroes if it grows. Shrinking a striped object
+    /// truncates or removes the RADOS objects beyond its new end.
+    pub fn truncate(&mut self, soid: &str, size: u64) -> Result<()> {
+        let stat = self.stat(soid)?;
+        let layout = stat.layout;
+
+        // The size is updated first, so that nobody reads the data being removed.
+        self.set_size(soid, size)?;
+
+        if size >= stat.size {
+            return Ok(());
+        }
+
+        let futures = (0..layout.object_count(stat.size))
+            .filter_map(|object_no| {
+                let old_len = layout.object_len(stat.size, object_no);
+                let new_len = layout.object_len(size, object_no);
+
+                if new_len == old_len {
+                    return None;
+                }
+
+                // The first object holds the metadata, and so is never removed.
+                let op = if new_len == 0 && object_no != 0 {
+                    WriteOp::new().remove()
+                } else {
+                    WriteOp::new().assert_exists().truncate(new_len)
+                };
+
+                Some(
+                    self.context
+                        .operate_async(&Self::object_name(soid, object_no), op),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // Objects in the striped object's holes may never have been created.
+        for res in futures.into_iter().map(block_on).collect::<Vec<_>>() {
+            match res {
+                Err(ref error) if not_found(error) => {}
+                res => res?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove a striped object, and every RADOS object backing it.
+    pub fn remove(&mut self, soid: &str) -> Result<()> {
+        let stat = self.stat(soid)?;
+
+        let futures = (1..stat.layout.object_count(stat.size))
+            .map(|object_no| {
+                self.context
+                    .remove_async(&Self::object_name(soid, object_no))
+            })
+            .collect::<Vec<_>>();
+
+        for res in futures.into_iter().map(block_on).collect::<Vec<_>>() {
+            match res {
+                Err(ref error) if not_found(error) => {}
+                res => res?,
+            }
+        }
+
+        // The first object goes last, so that a failed removal can be retried.
+        self.context.remove(&Self::object_name(soid, 0))
+    }
+}
+
+impl Context {
+    /// Create a `Striper` for reading and writing striped objects in this context's pool and
+    /// namespace, which creates striped objects with the default `StripeLayout`. The striper uses
+    /// its own I/O context, with the same locator key, read snapshot and write snapshot context as
+    /// this one, so striped writes are covered by this context's self-managed snapshots.
+    pub fn striper(&mut self) -> Result<Striper> {
+        Ok(Striper {
+            context: self.duplicate()?,
+            layout: StripeLayout::default(),
+        })
+    }
+}
@@ -0,0 +1,484 @@
+//! Watching RADOS objects for notifications, and sending notifications to the watchers of an
+//! object. Together these form a simple pub/sub mechanism, useful for things like cache
+//! invalidation across processes.
+
+use std::ffi::CString;
+use std::mem;
+use std::ptr;
+use std::result::Result as StdResult;
+use std::slice;
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::task::Poll;
+use std::time::Duration;
+
+use ceph::rados::{self, rados_ioctx_t};
+use libc;
+
+use async::{Completion, RadosFuture, RadosStream, Wake, WakerSlot};
+use errors::{self, Error, ErrorKind, Result};
+use ffi;
+
+use super::{Context, POOL};
+
+/// An event received by a `Watch`.
+#[derive(Debug)]
+pub enum WatchEvent {
+    /// A notification sent to the watched object. It has already been acknowledged, with an
+    /// empty reply payload.
+    Notify {
+        /// The ID of this notification.
+        notify_id: u64,
+
+        /// The global ID of the client which sent the notification.
+        notifier_id: u64,
+
+        /// The payload sent along with the notification.
+        payload: Vec<u8>,
+    },
+
+    /// The connection to the OSD holding the watch was lost, so notifications may have been
+    /// missed. The watch is no longer registered and must be re-established with
+    /// `Watch::rewatch`.
+    Disconnected,
+
+    /// The watch failed with some other error. As with `Disconnected`, it must be re-established
+    /// with `Watch::rewatch` before further notifications are received.
+    Error(Error),
+}
+
+/// The state shared between a `Watch` and the librados callbacks registered for it. It is boxed so
+/// that its address, which is handed to librados, is stable.
+#[derive(Debug)]
+struct WatchShared {
+    io: rados_ioctx_t,
+    object_id: CString,
+    waker: WakerSlot,
+    sender: Mutex<Sender<WatchEvent>>,
+}
+
+// The only thing in here which isn't `Send`/`Sync` is the ioctx handle, which is only used to
+// acknowledge notifications. librados allows this from its callback threads.
+unsafe impl Send for WatchShared {}
+unsafe impl Sync for WatchShared {}
+
+impl WatchShared {
+    fn push(&self, event: WatchEvent) {
+        // This runs inside librados callbacks, so it must not panic. A `Sender` has no invariants
+        // which a panic while the lock was held could have broken, so a poisoned lock is fine to
+        // use anyway.
+        let sender = match self.sender.lock() {
+            Ok(sender) => sender,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        // A failed send means the `Watch` is being dropped, in which case nobody cares.
+        let _ = sender.send(event);
+        mem::drop(sender);
+
+        self.waker.wake();
+    }
+}
+
+/// The notification callback passed into librados.
+extern "C" fn watch_callback(
+    arg: *mut libc::c_void,
+    notify_id: u64,
+    cookie: u64,
+    notifier_id: u64,
+    data: *mut libc::c_void,
+    data_len: libc::size_t,
+) {
+    let shared = unsafe { &*(arg as *const WatchShared) };
+
+    let payload = if data.is_null() {
+        Vec::new()
+    } else {
+        unsafe { slice::from_raw_parts(data as *const u8, data_len).to_vec() }
+    };
+
+    // Acknowledge the notification immediately, so that the notifier isn't left waiting on us.
+    // If this fails, the notifier will simply see this watcher time out.
+    unsafe {
+        rados::rados_notify_ack(
+            shared.io,
+            shared.object_id.as_ptr(),
+            notify_id,
+            cookie,
+            ptr::null(),
+            0,
+        );
+    }
+
+    shared.push(WatchEvent::Notify {
+        notify_id,
+        notifier_id,
+        payload,
+    });
+}
+
+/// The watch error callback passed into librados.
+extern "C" fn watch_error_callback(arg: *mut libc::c_void, _cookie: u64, err: libc::c_int) {
+    let shared = unsafe { &*(arg as *const WatchShared) };
+
+    let event = if err == -libc::ENOTCONN {
+        WatchEvent::Disconnected
+    } else {
+        WatchEvent::Error(ErrorKind::Rados(-err as u32).into())
+    };
+
+    shared.push(event);
+}
+
+/// A watch registered on a RADOS object, yielding a `WatchEvent` for every notification sent to
+/// the object and for every error encountered by the watch. The stream never ends by itself;
+/// dropping it unregisters the watch.
+#[derive(Debug)]
+pub struct Watch {
+    // The watch holds its own I/O context, so that it is independent of the context it was
+    // created from.
+    context: Context,
+    shared: Box<WatchShared>,
+    receiver: Receiver<WatchEvent>,
+    timeout: u32,
+    cookie: Option<u64>,
+}
+
+impl Watch {
+    fn register(&mut self) -> Result<()> {
+        let mut cookie = 0;
+        let shared_ptr = &*self.shared as *const WatchShared as *mut libc::c_void;
+
+        errors::librados(unsafe {
+            ffi::rados_watch3(
+                self.context.handle,
+                self.shared.object_id.as_ptr(),
+                &mut cookie,
+                Some(watch_callback),
+                Some(watch_error_callback),
+                self.timeout,
+                shared_ptr,
+            )
+        })?;
+
+        self.cookie = Some(cookie);
+
+        Ok(())
+    }
+
+    fn unregister(&mut self) -> Result<()> {
+        match self.cookie.take() {
+            Some(cookie) => errors::librados(unsafe {
+                rados::rados_unwatch2(self.context.handle, cookie)
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Check that the watch is still registered, using `rados_watch_check`. Returns the time
+    /// since the watch was last confirmed by the OSD.
+    pub fn check(&mut self) -> Result<Duration> {
+        match self.cookie {
+            Some(cookie) => {
+                let millis = errors::librados_res(unsafe {
+                    rados::rados_watch_check(self.context.handle, cookie)
+                })?;
+
+                Ok(Duration::from_millis(millis as u64))
+            }
+            None => bail!(ErrorKind::Rados(libc::ENOTCONN as u32)),
+        }
+    }
+
+    /// Re-establish the watch after a `WatchEvent::Disconnected` or `WatchEvent::Error`, using
+    /// `rados_unwatch2` followed by `rados_watch3`. Notifications sent in between are lost.
+    pub fn rewatch(&mut self) -> Result<()> {
+        // The old watch is most likely already gone, so a failure to remove it is irrelevant.
+        let _ = self.unregister();
+        self.register()
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        let _ = self.unregister();
+
+        // Wait for any callbacks which are still running, since they hold a pointer to the shared
+        // state which is about to be freed.
+        unsafe {
+            rados::rados_watch_flush(self.context.conn.handle);
+        }
+    }
+}
+
+impl RadosStream for Watch {
+    type Item = WatchEvent;
+
+    fn poll_next_rados(&mut self, wake: &Wake) -> Poll<Option<Result<Self::Item>>> {
+        // The task must be registered before checking for an event, so that an event pushed in
+        // between cannot be missed.
+        self.shared.waker.register(wake);
+
+        match self.receiver.try_recv() {
+            Ok(event) => Poll::Ready(Some(Ok(event))),
+            Err(TryRecvError::Empty) => Poll::Pending,
+
+            // The `WatchShared` owns the sender and lives as long as the `Watch` itself.
+            Err(TryRecvError::Disconnected) => unreachable!(),
+        }
+    }
+}
+
+rados_stream!(Watch);
+
+/// Identifies a single watch on an object: the global ID of the watching client, along with the
+/// cookie of the watch.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct WatcherId {
+    pub gid: u64,
+    pub cookie: u64,
+}
+
+/// An acknowledgement of a notification by a single watcher.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NotifyAck {
+    pub watcher: WatcherId,
+
+    /// The payload the watcher replied with; empty for watchers using this library.
+    pub payload: Vec<u8>,
+}
+
+/// The outcome of a notification: every watcher either acknowledged it or timed out.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NotifyResponse {
+    pub acks: Vec<NotifyAck>,
+    pub timeouts: Vec<WatcherId>,
+}
+
+/// A minimal decoder for the reply buffer of `rados_notify2`, which is an encoded
+/// `map<pair<uint64_t, uint64_t>, bufferlist>` of acks followed by an encoded
+/// `set<pair<uint64_t, uint64_t>>` of timed-out watchers, all little-endian.
+struct ReplyDecoder<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> ReplyDecoder<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.buf.len() < n {
+            bail!("malformed notify reply buffer");
+        }
+
+        let (head, tail) = self.buf.split_at(n);
+        self.buf = tail;
+
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.bytes(4)?;
+
+        Ok(bytes
+            .iter()
+            .rev()
+            .fold(0, |acc, &byte| (acc << 8) | byte as u32))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        let low = self.u32()? as u64;
+        let high = self.u32()? as u64;
+
+        Ok((high << 32) | low)
+    }
+
+    fn watcher(&mut self) -> Result<WatcherId> {
+        Ok(WatcherId {
+            gid: self.u64()?,
+            cookie: self.u64()?,
+        })
+    }
+
+    fn response(&mut self) -> Result<NotifyResponse> {
+        let mut response = NotifyResponse::default();
+
+        for _ in 0..self.u32()? {
+            let watcher = self.watcher()?;
+            let len = self.u32()? as usize;
+
+            response.acks.push(NotifyAck {
+                watcher,
+                payload: self.bytes(len)?.to_vec(),
+            });
+        }
+
+        for _ in 0..self.u32()? {
+            response.timeouts.push(self.watcher()?);
+        }
+
+        Ok(response)
+    }
+}
+
+/// The reply buffer allocated by librados for a notification, freed with `rados_buffer_free`.
+#[derive(Debug)]
+struct ReplyBuffer {
+    buf: *mut libc::c_char,
+    len: libc::size_t,
+}
+
+unsafe impl Send for ReplyBuffer {}
+
+impl ReplyBuffer {
+    fn new() -> ReplyBuffer {
+        ReplyBuffer {
+            buf: ptr::null_mut(),
+            len: 0,
+        }
+    }
+
+    /// Decode the buffer, given the return code of the notification. A timeout still fills in
+    /// the buffer, with the timed-out watchers listed separately, so it is not an error here.
+    fn decode(&self, err: i32) -> Result<NotifyResponse> {
+        if err != -libc::ETIMEDOUT {
+            errors::librados(err)?;
+        }
+
+        if self.buf.is_null() {
+            return Ok(NotifyResponse::default());
+        }
+
+        let buf = unsafe { slice::from_raw_parts(self.buf as *const u8, self.len) };
+
+        ReplyDecoder { buf }.response()
+    }
+}
+
+impl Drop for ReplyBuffer {
+    fn drop(&mut self) {
+        if !self.buf.is_null() {
+            unsafe {
+                rados::rados_buffer_free(self.buf);
+            }
+        }
+    }
+}
+
+/// The type of a notification which has yet to be acknowledged by every watcher, or to time
+/// out.
+#[derive(Debug)]
+pub struct NotifyFuture {
+    completion_res: StdResult<Completion<Box<ReplyBuffer>>, Option<Error>>,
+}
+
+impl RadosFuture for NotifyFuture {
+    type Item = NotifyResponse;
+
+    fn poll_rados(&mut self, wake: &Wake) -> Poll<Result<Self::Item>> {
+        match self.completion_res.as_mut() {
+            Ok(completion) => completion
+                .poll_raw(wake)
+                .map(|(err, reply)| reply.decode(err)),
+            Err(error) => Poll::Ready(Err(error.take().unwrap())),
+        }
+    }
+}
+
+rados_future!([] NotifyFuture);
+
+fn duration_millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + (duration.subsec_nanos() / 1_000_000) as u64
+}
+
+impl Context {
+    /// Watch an object for notifications, using `rados_watch3` with the default watch timeout.
+    pub fn watch(&mut self, obj: &str) -> Result<Watch> {
+        self.watch_with_timeout(obj, 0)
+    }
+
+    /// Watch an object for notifications, using `rados_watch3`. If the OSD does not hear from
+    /// this client for `timeout` seconds, it considers the watch dead; a timeout of zero uses the
+    /// cluster's default.
+    pub fn watch_with_timeout(&mut self, obj: &str, timeout: u32) -> Result<Watch> {
+        let context = self.duplicate()?;
+        let (sender, receiver) = mpsc::channel();
+
+        let mut watch = Watch {
+            shared: Box::new(WatchShared {
+                io: context.handle,
+                object_id: CString::new(obj)?,
+                waker: WakerSlot::new(),
+                sender: Mutex::new(sender),
+            }),
+            context,
+            receiver,
+            timeout,
+            cookie: None,
+        };
+
+        watch.register()?;
+
+        Ok(watch)
+    }
+
+    /// Send a notification to every watcher of an object and wait for them to acknowledge it,
+    /// using `rados_notify2`. Watchers which do not respond within `timeout` are listed in the
+    /// response rather than causing an error.
+    pub fn notify(
+        &mut self,
+        obj: &str,
+        payload: &[u8],
+        timeout: Duration,
+    ) -> Result<NotifyResponse> {
+        let object_id = POOL.get_str(obj)?;
+        let mut reply = ReplyBuffer::new();
+
+        let err = unsafe {
+            rados::rados_notify2(
+                self.handle,
+                object_id.as_ptr(),
+                payload.as_ptr() as *const libc::c_char,
+                payload.len() as libc::c_int,
+                duration_millis(timeout),
+                &mut reply.buf,
+                &mut reply.len,
+            )
+        };
+
+        mem::drop(object_id);
+
+        reply.decode(err)
+    }
+
+    /// Asynchronously send a notification to every watcher of an object, using
+    /// `rados_aio_notify`. The future resolves once every watcher has acknowledged the
+    /// notification or timed out.
+    pub fn notify_async(&mut self, obj: &str, payload: &[u8], timeout: Duration) -> NotifyFuture {
+        let mut boxed = Box::new(ReplyBuffer::new());
+        let buf_ptr = &mut boxed.buf as *mut *mut libc::c_char;
+        let len_ptr = &mut boxed.len as *mut libc::size_t;
+
+        let completion_res = Completion::new(boxed, |completion_handle| {
+            let object_id = POOL.get_str(obj)?;
+
+            errors::librados(unsafe {
+                ffi::rados_aio_notify(
+                    self.handle,
+                    object_id.as_ptr(),
+                    completion_handle,
+                    payload.as_ptr() as *const libc::c_char,
+                    payload.len() as libc::c_int,
+                    duration_millis(timeout),
+                    buf_ptr,
+                    len_ptr,
+                )
+            })?;
+
+            mem::drop(object_id);
+
+            Ok(())
+        });
+
+        NotifyFuture {
+            completion_res: completion_res.map_err(Some),
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use std::io::SeekFrom;
+
+use futures_executor::block_on;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use super::{CLUSTER_HOLD, connect_to_cluster};
+
+
+#[test]
+fn async_object_handle() {
+    let lock = CLUSTER_HOLD.lock().unwrap();
+
+    let mut cluster = connect_to_cluster().unwrap();
+    let mut pool = cluster.get_pool_context("rbd").unwrap();
+
+    let data = (0..100u32).map(|i| i as u8).collect::<Vec<u8>>();
+
+    let mut handle = pool.open_async("async-handle-obj").unwrap();
+    handle.set_chunk_size(16);
+    handle.set_readahead(3);
+    handle.set_writes_in_flight(2);
+
+    block_on(handle.write_all(&data)).unwrap();
+    block_on(handle.flush()).unwrap();
+
+    assert_eq!(block_on(handle.seek(SeekFrom::End(-10))).unwrap(), 90);
+
+    let mut tail = Vec::new();
+    block_on(handle.read_to_end(&mut tail)).unwrap();
+    assert_eq!(&tail[..], &data[90..]);
+
+    block_on(handle.seek(SeekFrom::Start(0))).unwrap();
+
+    let mut contents = Vec::new();
+    block_on(handle.read_to_end(&mut contents)).unwrap();
+    assert_eq!(contents, data);
+
+    block_on(handle.shutdown()).unwrap();
+
+    assert_eq!(pool.stat("async-handle-obj").unwrap().size, 100);
+
+    pool.remove("async-handle-obj").unwrap();
+
+    let _ = lock;
+}
+
+
+#[test]
+fn async_object_handle_failed_writes() {
+    let lock = CLUSTER_HOLD.lock().unwrap();
+
+    let mut cluster = connect_to_cluster().unwrap();
+    let mut pool = cluster.get_pool_context("rbd").unwrap();
+
+    // librados refuses writes through a context which reads from a snapshot, and the handle
+    // inherits the read snapshot, so every write it makes fails.
+    pool.set_read_snapshot(Some(1));
+    let mut handle = pool.open_async("async-handle-failed-obj").unwrap();
+    pool.set_read_snapshot(None);
+
+    handle.set_chunk_size(16);
+    handle.set_writes_in_flight(1);
+
+    // The failed write must be reported once, and not polled again by later calls.
+    assert!(block_on(handle.write_all(&[0; 40])).is_err());
+    assert!(block_on(handle.shutdown()).is_err());
+    block_on(handle.shutdown()).unwrap();
+
+    // The same goes for the writes flushed by a seek relative to the end of the object.
+    block_on(handle.write_all(&[0; 8])).unwrap();
+    assert!(block_on(handle.seek(SeekFrom::End(0))).is_err());
+    block_on(handle.shutdown()).unwrap();
+
+    assert!(!pool.exists("async-handle-failed-obj").unwrap());
+
+    let _ = lock;
+}
@@ -0,0 +1,53 @@
+use futures::prelude::*;
+
+use rad::PgId;
+
+use super::{CLUSTER_HOLD, connect_to_cluster};
+
+
+#[test]
+fn mon_command() {
+    let lock = CLUSTER_HOLD.lock().unwrap();
+
+    let mut cluster = connect_to_cluster().unwrap();
+
+    let output = cluster
+        .mon_command(r#"{"prefix": "health", "format": "json"}"#, b"")
+        .unwrap();
+    assert!(output.data_str().unwrap().contains("status"));
+
+    let output = cluster
+        .mon_command_async(r#"{"prefix": "df", "format": "json"}"#, b"")
+        .wait()
+        .unwrap();
+    assert!(output.data_str().unwrap().contains("pools"));
+
+    assert!(cluster.mon_command(r#"{"prefix": "no such command"}"#, b"").is_err());
+
+    let _ = lock;
+}
+
+
+#[test]
+fn osd_and_pg_commands() {
+    let lock = CLUSTER_HOLD.lock().unwrap();
+
+    let mut cluster = connect_to_cluster().unwrap();
+
+    let output = cluster.osd_command(0, r#"{"prefix": "version"}"#, b"").unwrap();
+    assert!(output.data_str().unwrap().contains("version"));
+
+    let pool_id = cluster.pool_lookup("rbd").unwrap();
+    let pg: PgId = format!("{}.0", pool_id).parse().unwrap();
+    assert_eq!(pg, PgId { pool: pool_id, seed: 0 });
+    assert_eq!("3.1f".parse::<PgId>().unwrap().to_string(), "3.1f");
+    assert!("3".parse::<PgId>().is_err());
+
+    let output = cluster
+        .pg_command_async(pg, r#"{"prefix": "query"}"#, b"")
+        .wait()
+        .unwrap();
+    assert!(output.data_str().unwrap().contains("state"));
+
+    let _ = lock;
+}
@@ -11,3 +11,25 @@ fn connect_to_ceph() {
 
     let _ = lock;
 }
+
+
+#[test]
+fn connection_identity() {
+    let lock = CLUSTER_HOLD.lock().unwrap();
+
+    let mut cluster = connect_to_cluster().unwrap();
+
+    assert_eq!(cluster.fsid().unwrap().len(), 36);
+    assert!(cluster.instance_id() > 0);
+    assert!(!cluster.get_addrs().unwrap().is_empty());
+    assert!(!cluster.conf_get("keyring").unwrap().is_empty());
+    cluster.wait_for_latest_osdmap().unwrap();
+
+    let mut pool = cluster.get_pool_context("rbd").unwrap();
+
+    assert_eq!(pool.pool_name().unwrap(), "rbd");
+    assert_eq!(pool.pool_id(), cluster.pool_lookup("rbd").unwrap());
+    assert!(!pool.pool_requires_alignment().unwrap());
+
+    let _ = lock;
+}
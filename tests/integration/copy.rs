@@ -0,0 +1,62 @@
+use super::{CLUSTER_HOLD, connect_to_cluster};
+
+
+#[test]
+fn copy_and_rename() {
+    let lock = CLUSTER_HOLD.lock().unwrap();
+
+    let mut cluster = connect_to_cluster().unwrap();
+    let mut pool = cluster.get_pool_context("rbd").unwrap();
+    let mut other = pool.with_namespace("rad-test-copy").unwrap();
+
+    let entries = (0..2000)
+        .map(|i| (format!("key-{:04}", i), format!("value-{}", i)))
+        .collect::<Vec<_>>();
+
+    pool.write_full("copy-src", b"copied data").unwrap();
+    pool.set_xattr("copy-src", "kind", b"source").unwrap();
+    pool.omap_set("copy-src", entries.iter().map(|&(ref k, ref v)| (k, v))).unwrap();
+
+    other.write_full("copy-dst", b"some much longer data to be replaced").unwrap();
+    other.set_xattr("copy-dst", "stale", b"old").unwrap();
+    other.omap_set("copy-dst", vec![("stale", "old")]).unwrap();
+    other.copy_from(&mut pool, "copy-src", "copy-dst").unwrap();
+
+    let mut buf = vec![0u8; 64];
+    let read = other.read("copy-dst", &mut buf, 0).unwrap();
+    assert_eq!(&buf[..read], b"copied data");
+    assert_eq!(other.get_xattr("copy-dst", "kind", 6).unwrap(), b"source");
+
+    let omap = other.omap_get("copy-dst", &["key-0000", "key-1999"]).unwrap();
+    assert_eq!(omap["key-1999"], b"value-1999");
+    assert_eq!(other.omap_vals("copy-dst", "key-1900", "", 1000).unwrap().entries.len(), 99);
+
+    // Nothing of the object being replaced may survive the copy.
+    assert!(other.get_xattr("copy-dst", "stale", 3).is_err());
+    assert!(other.omap_get("copy-dst", &["stale"]).unwrap().is_empty());
+
+    // The copy is written straight to the destination, without any other objects.
+    let listed = other.list_objects()
+        .map(|entry| entry.unwrap().name)
+        .collect::<Vec<_>>();
+    assert_eq!(listed, vec!["copy-dst".to_owned()]);
+
+    // Copying an object onto itself must fail without touching it.
+    let mut same = pool.with_namespace("").unwrap();
+    assert!(pool.copy_from(&mut same, "copy-src", "copy-src").is_err());
+
+    let read = pool.read("copy-src", &mut buf, 0).unwrap();
+    assert_eq!(&buf[..read], b"copied data");
+    assert_eq!(pool.omap_vals("copy-src", "", "", 3000).unwrap().entries.len(), 2000);
+
+    pool.rename("copy-src", "copy-renamed").unwrap();
+    assert!(!pool.exists("copy-src").unwrap());
+
+    let read = pool.read("copy-renamed", &mut buf, 0).unwrap();
+    assert_eq!(&buf[..read], b"copied data");
+
+    pool.remove("copy-renamed").unwrap();
+    other.remove("copy-dst").unwrap();
+
+    let _ = lock;
+}
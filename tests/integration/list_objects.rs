@@ -0,0 +1,133 @@
+use std::collections::HashSet;
+use std::thread;
+
+use futures::prelude::*;
+
+use super::{CLUSTER_HOLD, connect_to_cluster};
+
+
+const NUM_OBJECTS: usize = 16;
+
+
+#[test]
+fn list_objects() {
+    let lock = CLUSTER_HOLD.lock().unwrap();
+
+    let mut cluster = connect_to_cluster().unwrap();
+    let mut pool = cluster.get_pool_context("rbd").unwrap();
+
+    let names: Vec<_> = (0..NUM_OBJECTS).map(|i| format!("list-obj-{}", i)).collect();
+
+    for name in &names {
+        pool.write_full(name, name.as_bytes()).unwrap();
+    }
+
+    let listed = pool.list_objects()
+        .map(|entry| entry.unwrap().name)
+        .collect::<HashSet<_>>();
+
+    for name in &names {
+        assert!(listed.contains(name));
+    }
+
+    // Take a cursor partway through and check that resuming from it picks up where we left off.
+    let (cursor, rest) = {
+        let mut iter = pool.list_objects();
+        iter.next().unwrap().unwrap();
+        let cursor = iter.cursor().unwrap();
+        let rest = iter.map(|entry| entry.unwrap()).collect::<Vec<_>>();
+
+        (cursor, rest)
+    };
+
+    let resumed = pool.list_objects_from(&cursor)
+        .map(|entry| entry.unwrap())
+        .collect::<Vec<_>>();
+
+    assert_eq!(rest, resumed);
+
+    for name in &names {
+        pool.remove(name).unwrap();
+    }
+
+    let _ = lock;
+}
+
+
+#[test]
+fn list_objects_stream() {
+    let lock = CLUSTER_HOLD.lock().unwrap();
+
+    let mut cluster = connect_to_cluster().unwrap();
+    let mut pool = cluster.get_pool_context("rbd").unwrap();
+
+    let names: Vec<_> = (0..NUM_OBJECTS).map(|i| format!("list-stream-obj-{}", i)).collect();
+
+    for name in &names {
+        pool.write_full(name, name.as_bytes()).unwrap();
+    }
+
+    let listed = pool.list_objects_stream(4)
+        .map(|entry| entry.name)
+        .collect()
+        .wait()
+        .unwrap()
+        .into_iter()
+        .collect::<HashSet<_>>();
+
+    for name in &names {
+        assert!(listed.contains(name));
+    }
+
+    for name in &names {
+        pool.remove(name).unwrap();
+    }
+
+    let _ = lock;
+}
+
+
+#[test]
+fn split_objects() {
+    let lock = CLUSTER_HOLD.lock().unwrap();
+
+    let mut cluster = connect_to_cluster().unwrap();
+    let mut pool = cluster.get_pool_context("rbd").unwrap();
+
+    let names: Vec<_> = (0..NUM_OBJECTS).map(|i| format!("list-split-obj-{}", i)).collect();
+
+    for name in &names {
+        pool.write_full(name, name.as_bytes()).unwrap();
+    }
+
+    let handles = pool.split_objects(4)
+        .unwrap()
+        .into_iter()
+        .map(|lister| {
+            thread::spawn(move || {
+                lister
+                    .map(|entry| entry.unwrap().name)
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut listed = HashSet::new();
+
+    for handle in handles {
+        for name in handle.join().unwrap() {
+            // Each object must be listed by exactly one of the listers.
+            assert!(listed.insert(name));
+        }
+    }
+
+    for name in &names {
+        assert!(listed.contains(name));
+    }
+
+    for name in &names {
+        pool.remove(name).unwrap();
+    }
+
+    let _ = lock;
+}
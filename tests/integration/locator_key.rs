@@ -0,0 +1,46 @@
+use super::{CLUSTER_HOLD, connect_to_cluster};
+
+
+#[test]
+fn locator_key() {
+    let lock = CLUSTER_HOLD.lock().unwrap();
+
+    let mut cluster = connect_to_cluster().unwrap();
+    let mut pool = cluster.get_pool_context("rbd").unwrap();
+
+    assert_eq!(pool.locator_key(), None);
+
+    pool.set_locator_key(Some("loc-key")).unwrap();
+    assert_eq!(pool.locator_key(), Some("loc-key"));
+
+    pool.write_full("loc-obj", b"located data").unwrap();
+
+    let mut buf = vec![0; 32];
+    let n = pool.read("loc-obj", &mut buf, 0).unwrap();
+    assert_eq!(&buf[..n], b"located data");
+
+    let listed = pool.list_objects()
+        .map(|entry| entry.unwrap())
+        .find(|entry| entry.name == "loc-obj")
+        .unwrap();
+    assert_eq!(listed.locator, Some("loc-key".to_owned()));
+
+    // Duplicated contexts carry the locator key over.
+    {
+        let mut dup = pool.with_namespace("").unwrap();
+        assert_eq!(dup.locator_key(), Some("loc-key"));
+
+        let n = dup.read("loc-obj", &mut buf, 0).unwrap();
+        assert_eq!(&buf[..n], b"located data");
+    }
+
+    // The locator key is part of an object's identity, so without it the object isn't found.
+    pool.set_locator_key(None).unwrap();
+    assert_eq!(pool.locator_key(), None);
+    assert!(!pool.exists("loc-obj").unwrap());
+
+    pool.set_locator_key(Some("loc-key")).unwrap();
+    pool.remove("loc-obj").unwrap();
+
+    let _ = lock;
+}
@@ -0,0 +1,57 @@
+use std::time::Duration;
+
+use rad::{Error, ErrorKind};
+
+use super::{CLUSTER_HOLD, connect_to_cluster};
+
+
+#[test]
+fn exclusive_lock() {
+    let lock = CLUSTER_HOLD.lock().unwrap();
+
+    let mut cluster = connect_to_cluster().unwrap();
+    let mut pool = cluster.get_pool_context("rbd").unwrap();
+
+    pool.write_full("locked-obj", b"").unwrap();
+
+    let mut guard = pool.lock_exclusive_guard(
+        "locked-obj",
+        "leader",
+        "first",
+        "elected leader",
+        Some(Duration::from_secs(30)),
+    ).unwrap();
+
+    match pool.lock_exclusive("locked-obj", "leader", "second", "", None) {
+        Err(Error(ErrorKind::Rados(err), _)) if err == 16 => {} // EBUSY
+        other => panic!("expected the lock to be busy, got {:?}", other),
+    }
+
+    let info = pool.list_lockers("locked-obj", "leader").unwrap();
+    assert!(info.exclusive);
+    assert_eq!(info.lockers.len(), 1);
+    assert_eq!(info.lockers[0].cookie, "first");
+
+    guard.renew().unwrap();
+    guard.renew_every(Duration::from_secs(10)).unwrap();
+    guard.check_renewal().unwrap();
+    guard.unlock().unwrap();
+
+    assert!(pool.list_lockers("locked-obj", "leader").unwrap().lockers.is_empty());
+
+    pool.lock_shared("locked-obj", "readers", "a", "tag", "", None).unwrap();
+    pool.lock_shared("locked-obj", "readers", "b", "tag", "", None).unwrap();
+
+    let info = pool.list_lockers("locked-obj", "readers").unwrap();
+    assert!(!info.exclusive);
+    assert_eq!(info.tag, "tag");
+    assert_eq!(info.lockers.len(), 2);
+
+    let client = info.lockers[0].client.clone();
+    pool.break_lock("locked-obj", "readers", &client, "a").unwrap();
+    pool.unlock("locked-obj", "readers", "b").unwrap();
+
+    pool.remove("locked-obj").unwrap();
+
+    let _ = lock;
+}
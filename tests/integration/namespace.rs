@@ -0,0 +1,36 @@
+use super::{CLUSTER_HOLD, connect_to_cluster};
+
+
+#[test]
+fn namespace_isolation() {
+    let lock = CLUSTER_HOLD.lock().unwrap();
+
+    let mut cluster = connect_to_cluster().unwrap();
+    let mut pool = cluster.get_pool_context("rbd").unwrap();
+    let mut tenant = pool.with_namespace("tenant-a").unwrap();
+
+    assert_eq!(pool.namespace().unwrap(), "");
+    assert_eq!(tenant.namespace().unwrap(), "tenant-a");
+
+    tenant.write_full("ns-obj", b"tenant data").unwrap();
+
+    assert!(tenant.exists("ns-obj").unwrap());
+    assert!(!pool.exists("ns-obj").unwrap());
+
+    let listed = tenant.list_objects()
+        .map(|entry| entry.unwrap())
+        .collect::<Vec<_>>();
+
+    assert!(listed.iter().all(|entry| entry.namespace == "tenant-a"));
+    assert!(listed.iter().any(|entry| entry.name == "ns-obj"));
+
+    pool.set_all_namespaces().unwrap();
+
+    assert!(pool.list_objects()
+        .map(|entry| entry.unwrap())
+        .any(|entry| entry.name == "ns-obj" && entry.namespace == "tenant-a"));
+
+    tenant.remove("ns-obj").unwrap();
+
+    let _ = lock;
+}
@@ -0,0 +1,88 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use rad::SnapContext;
+
+use super::{CLUSTER_HOLD, connect_to_cluster};
+
+
+#[test]
+fn object_handle() {
+    let lock = CLUSTER_HOLD.lock().unwrap();
+
+    let mut cluster = connect_to_cluster().unwrap();
+    let mut pool = cluster.get_pool_context("rbd").unwrap();
+
+    let mut handle = pool.open("object-handle-obj").unwrap();
+    handle.set_buffer_size(16);
+
+    for i in 0..10u8 {
+        handle.write_all(&[i; 5]).unwrap();
+    }
+    assert_eq!(handle.position(), 50);
+
+    handle.seek(SeekFrom::Start(20)).unwrap();
+    handle.write_all(b"xyz").unwrap();
+    handle.flush().unwrap();
+
+    assert_eq!(handle.seek(SeekFrom::End(-2)).unwrap(), 48);
+
+    let mut tail = Vec::new();
+    handle.read_to_end(&mut tail).unwrap();
+    assert_eq!(tail, [9, 9]);
+
+    handle.seek(SeekFrom::Start(0)).unwrap();
+    let mut contents = Vec::new();
+    handle.read_to_end(&mut contents).unwrap();
+    handle.finish().unwrap();
+
+    let mut expected = (0..10u8).flat_map(|i| vec![i; 5]).collect::<Vec<u8>>();
+    expected[20..23].copy_from_slice(b"xyz");
+    assert_eq!(contents, expected);
+
+    let mut from_cluster = vec![0u8; 64];
+    let read = pool.read("object-handle-obj", &mut from_cluster, 0).unwrap();
+    assert_eq!(&from_cluster[..read], &expected[..]);
+
+    pool.remove("object-handle-obj").unwrap();
+
+    let _ = lock;
+}
+
+
+#[test]
+fn object_handle_snap_context() {
+    let lock = CLUSTER_HOLD.lock().unwrap();
+
+    let mut cluster = connect_to_cluster().unwrap();
+
+    // Self-managed snapshots need a pool of their own, created by `run-all-tests.sh`.
+    let mut pool = cluster.get_pool_context("rad-test-selfsnap").unwrap();
+    let mut snapc = SnapContext::new();
+
+    pool.write_full("handle-snap-obj", b"before").unwrap();
+
+    let snap = pool.selfmanaged_snap_create().unwrap();
+    snapc.push(snap);
+    pool.set_write_snap_context(&snapc).unwrap();
+
+    // The handle must write under the snapshot context of the context it was opened from, or the
+    // snapshot would not preserve the object's earlier contents.
+    let mut handle = pool.open("handle-snap-obj").unwrap();
+    handle.write_all(b"after!").unwrap();
+    handle.finish().unwrap();
+
+    let mut buf = vec![0; 16];
+
+    let n = pool.read("handle-snap-obj", &mut buf, 0).unwrap();
+    assert_eq!(&buf[..n], b"after!");
+
+    pool.set_read_snapshot(Some(snap));
+    let n = pool.read("handle-snap-obj", &mut buf, 0).unwrap();
+    assert_eq!(&buf[..n], b"before");
+    pool.set_read_snapshot(None);
+
+    pool.selfmanaged_snap_remove(snap).unwrap();
+    pool.remove("handle-snap-obj").unwrap();
+
+    let _ = lock;
+}
@@ -0,0 +1,43 @@
+use futures::prelude::*;
+
+use super::{CLUSTER_HOLD, connect_to_cluster};
+
+
+#[test]
+fn omap() {
+    let lock = CLUSTER_HOLD.lock().unwrap();
+
+    let mut cluster = connect_to_cluster().unwrap();
+    let mut pool = cluster.get_pool_context("rbd").unwrap();
+
+    let entries = (0..16)
+        .map(|i| (format!("key-{:02}", i), format!("value-{}", i)))
+        .collect::<Vec<_>>();
+
+    pool.omap_set("omap-obj", entries.iter().map(|&(ref k, ref v)| (k, v))).unwrap();
+
+    let got = pool.omap_get("omap-obj", &["key-03", "key-07", "missing"]).unwrap();
+    assert_eq!(got.len(), 2);
+    assert_eq!(got["key-03"], b"value-3");
+
+    let first = pool.omap_keys("omap-obj", "", 10).unwrap();
+    assert_eq!(first.entries.len(), 10);
+    assert!(first.more);
+
+    let second = pool.omap_keys("omap-obj", first.entries.last().unwrap(), 10).unwrap();
+    assert_eq!(second.entries.len(), 6);
+    assert!(!second.more);
+
+    let vals = pool.omap_vals_async("omap-obj", "", "key-1", 100).wait().unwrap();
+    assert_eq!(vals.entries.len(), 6);
+
+    pool.omap_rm_keys("omap-obj", &["key-00"]).unwrap();
+    assert!(pool.omap_get("omap-obj", &["key-00"]).unwrap().is_empty());
+
+    pool.omap_clear_async("omap-obj").wait().unwrap();
+    assert!(pool.omap_keys("omap-obj", "", 100).unwrap().entries.is_empty());
+
+    pool.remove("omap-obj").unwrap();
+
+    let _ = lock;
+}
@@ -0,0 +1,217 @@
+use futures::prelude::*;
+
+use rad::{CmpOp, Error, ErrorKind, ReadOp, WriteOp};
+
+use super::{CLUSTER_HOLD, connect_to_cluster};
+
+
+#[test]
+fn compound_write() {
+    let lock = CLUSTER_HOLD.lock().unwrap();
+
+    let mut cluster = connect_to_cluster().unwrap();
+    let mut pool = cluster.get_pool_context("rbd").unwrap();
+
+    let op = WriteOp::new()
+        .create(true)
+        .write_full(b"first")
+        .set_xattr("owner", b"alice")
+        .unwrap()
+        .omap_set(vec![("state", "new")])
+        .unwrap();
+
+    pool.operate("op-obj", op).unwrap();
+
+    assert_eq!(pool.get_xattr("op-obj", "owner", 5).unwrap(), b"alice");
+
+    // An exclusive create of an existing object must fail, and take the rest of the operation
+    // down with it.
+    let op = WriteOp::new().create(true).write_full(b"second");
+    assert!(pool.operate("op-obj", op).is_err());
+
+    // A failed guard must also prevent the write.
+    let op = WriteOp::new()
+        .cmp_xattr("owner", CmpOp::Eq, b"bob")
+        .unwrap()
+        .write_full(b"second");
+    assert!(pool.operate("op-obj", op).is_err());
+
+    let mut buf = [0u8; 5];
+    pool.read("op-obj", &mut buf, 0).unwrap();
+    assert_eq!(&buf, b"first");
+
+    pool.operate("op-obj", WriteOp::new().assert_exists().remove()).unwrap();
+    assert!(!pool.exists("op-obj").unwrap());
+
+    let _ = lock;
+}
+
+
+#[test]
+fn compound_read() {
+    let lock = CLUSTER_HOLD.lock().unwrap();
+
+    let mut cluster = connect_to_cluster().unwrap();
+    let mut pool = cluster.get_pool_context("rbd").unwrap();
+
+    let op = WriteOp::new()
+        .write_full(b"0123456789")
+        .set_xattr("kind", b"digits")
+        .unwrap();
+    pool.operate("read-op-obj", op).unwrap();
+
+    let mut op = ReadOp::new();
+    op.assert_exists();
+    let data = op.read(2, 4);
+    let stat = op.stat();
+    let xattrs = op.xattrs();
+
+    let mut completed = pool.read_op_async("read-op-obj", op).wait().unwrap();
+
+    assert_eq!(completed.take(data).unwrap(), b"2345");
+    assert_eq!(completed.take(stat).unwrap().size, 10);
+    assert_eq!(completed.take(xattrs).unwrap()["kind"], b"digits");
+
+    let mut op = ReadOp::new();
+    op.cmp_xattr("kind", CmpOp::Eq, b"letters").unwrap();
+    op.read(0, 10);
+    assert!(pool.read_op("read-op-obj", op).is_err());
+
+    // An output handle from another operation with the same layout must be refused rather than
+    // redeemed for this operation's result.
+    let mut op = ReadOp::new();
+    op.read(0, 4);
+    let mut other_op = ReadOp::new();
+    let other_data = other_op.read(4, 4);
+
+    let mut completed = pool.read_op("read-op-obj", op).unwrap();
+    assert!(completed.take(other_data).is_err());
+
+    pool.remove("read-op-obj").unwrap();
+
+    let _ = lock;
+}
+
+
+#[test]
+fn compare_and_swap() {
+    let lock = CLUSTER_HOLD.lock().unwrap();
+
+    let mut cluster = connect_to_cluster().unwrap();
+    let mut pool = cluster.get_pool_context("rbd").unwrap();
+
+    pool.write_full("cas-obj", b"abcdef").unwrap();
+    pool.set_xattr("cas-obj", "gen", b"1").unwrap();
+
+    pool.cmp_ext("cas-obj", b"cde", 2).unwrap();
+
+    match pool.cmp_ext("cas-obj", b"cdX", 2) {
+        Err(Error(ErrorKind::ExtentMismatch(2), _)) => {}
+        other => panic!("expected a mismatch at offset 2, got {:?}", other),
+    }
+
+    pool.cmp_xattr("cas-obj", "gen", CmpOp::Eq, b"1").unwrap();
+
+    // Swap the generation only if it is still the one we read.
+    let op = WriteOp::new()
+        .cmp_xattr("gen", CmpOp::Eq, b"1")
+        .unwrap()
+        .set_xattr("gen", b"2")
+        .unwrap()
+        .write_full(b"ghijkl");
+    pool.operate("cas-obj", op).unwrap();
+
+    let op = WriteOp::new()
+        .cmp_xattr("gen", CmpOp::Eq, b"1")
+        .unwrap()
+        .write_full(b"stale");
+
+    match pool.operate("cas-obj", op) {
+        Err(Error(ErrorKind::ComparisonFailed, _)) => {}
+        other => panic!("expected a failed comparison, got {:?}", other),
+    }
+
+    match pool.operate("cas-obj", WriteOp::new().cmp_ext(b"ghX", 0).remove()) {
+        Err(Error(ErrorKind::ExtentMismatch(2), _)) => {}
+        other => panic!("expected a mismatch at offset 2, got {:?}", other),
+    }
+
+    match pool.cmp_ext_async("cas-obj", b"gXi", 0).wait() {
+        Err(Error(ErrorKind::ExtentMismatch(1), _)) => {}
+        other => panic!("expected a mismatch at offset 1, got {:?}", other),
+    }
+
+    // The OSD only implements equality and strict ordering for omap comparisons.
+    pool.omap_set("cas-obj", vec![("gen", "2")]).unwrap();
+    pool.omap_cmp("cas-obj", "gen", CmpOp::Lt, b"3").unwrap();
+    assert!(WriteOp::new().omap_cmp("gen", CmpOp::Ne, b"3").is_err());
+
+    pool.remove("cas-obj").unwrap();
+
+    let _ = lock;
+}
+
+
+#[test]
+fn conditional_version() {
+    let lock = CLUSTER_HOLD.lock().unwrap();
+
+    let mut cluster = connect_to_cluster().unwrap();
+    let mut pool = cluster.get_pool_context("rbd").unwrap();
+
+    pool.write_full("version-obj", b"one").unwrap();
+    let version = pool.stat("version-obj").unwrap().version;
+    assert_eq!(pool.last_version(), version);
+
+    let op = WriteOp::new().assert_version(version).write_full(b"two");
+    let new_version = pool
+        .operate_async("version-obj", op)
+        .versioned()
+        .wait()
+        .unwrap();
+    assert!(new_version > version);
+
+    // The object has moved on, so a write conditional on the old version must fail.
+    let op = WriteOp::new().assert_version(version).write_full(b"stale");
+    assert!(pool.operate("version-obj", op).is_err());
+
+    let stat = pool.stat_async("version-obj").wait().unwrap();
+    assert_eq!(stat.version, new_version);
+
+    pool.remove("version-obj").unwrap();
+
+    let _ = lock;
+}
+
+
+#[test]
+fn stat2_and_extended_stat() {
+    let lock = CLUSTER_HOLD.lock().unwrap();
+
+    let mut cluster = connect_to_cluster().unwrap();
+    let mut pool = cluster.get_pool_context("rbd").unwrap();
+
+    pool.write_full("stat2-obj", b"nanoseconds").unwrap();
+    pool.set_xattr("stat2-obj", "b", b"2").unwrap();
+    pool.set_xattr("stat2-obj", "a", b"1").unwrap();
+
+    let stat = pool.stat("stat2-obj").unwrap();
+    let stat2 = pool.stat2("stat2-obj").unwrap();
+    assert_eq!(stat2.size, 11);
+    assert_eq!(stat2.version, stat.version);
+    assert_eq!(stat2.last_modified.timestamp(), stat.last_modified.timestamp());
+    assert_eq!(pool.stat2_async("stat2-obj").wait().unwrap(), stat2);
+
+    let extended = pool.stat_extended("stat2-obj", true).unwrap();
+    assert_eq!(extended.size, 11);
+    assert_eq!(extended.last_modified, stat2.last_modified);
+    assert_eq!(extended.version, stat2.version);
+    assert_eq!(extended.xattr_names, Some(vec!["a".to_owned(), "b".to_owned()]));
+
+    let extended = pool.stat_extended_async("stat2-obj", false).wait().unwrap();
+    assert_eq!(extended.xattr_names, None);
+
+    pool.remove("stat2-obj").unwrap();
+
+    let _ = lock;
+}
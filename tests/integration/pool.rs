@@ -0,0 +1,57 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{CLUSTER_HOLD, connect_to_cluster};
+
+
+#[test]
+fn pool_administration() {
+    let lock = CLUSTER_HOLD.lock().unwrap();
+
+    let mut cluster = connect_to_cluster().unwrap();
+
+    cluster.create_pool("rad-test-pool").unwrap();
+    assert!(cluster.list_pools().unwrap().iter().any(|name| name == "rad-test-pool"));
+
+    let id = cluster.pool_lookup("rad-test-pool").unwrap();
+    assert_eq!(cluster.pool_reverse_lookup(id).unwrap(), "rad-test-pool");
+
+    cluster.delete_pool("rad-test-pool").unwrap();
+    assert!(cluster.pool_lookup("rad-test-pool").is_err());
+
+    let _ = lock;
+}
+
+
+#[test]
+fn pool_stat() {
+    let lock = CLUSTER_HOLD.lock().unwrap();
+
+    let mut cluster = connect_to_cluster().unwrap();
+    let mut pool = cluster.get_pool_context("rbd").unwrap();
+
+    pool.write_full("pool-stat-obj", b"some data").unwrap();
+
+    // OSDs report pool statistics to the monitors periodically, so the write may take a while to
+    // show up.
+    let deadline = Instant::now() + Duration::from_secs(60);
+
+    loop {
+        let stat = pool.pool_stat().unwrap();
+
+        if stat.num_objects >= 1 && stat.num_wr >= 1 {
+            break;
+        }
+
+        assert!(
+            Instant::now() < deadline,
+            "pool statistics never reflected the write: {:?}",
+            stat
+        );
+        thread::sleep(Duration::from_secs(1));
+    }
+
+    pool.remove("pool-stat-obj").unwrap();
+
+    let _ = lock;
+}
@@ -0,0 +1,78 @@
+use futures::prelude::*;
+
+use rad::SnapContext;
+
+use super::{CLUSTER_HOLD, connect_to_cluster};
+
+
+#[test]
+fn pool_snapshot() {
+    let lock = CLUSTER_HOLD.lock().unwrap();
+
+    let mut cluster = connect_to_cluster().unwrap();
+    let mut pool = cluster.get_pool_context("rbd").unwrap();
+
+    pool.write_full("snap-obj", b"before").unwrap();
+    pool.snap_create("rad-test-snap").unwrap();
+    pool.write_full("snap-obj", b"after").unwrap();
+
+    let id = pool.snap_lookup("rad-test-snap").unwrap();
+    assert_eq!(pool.snap_name(id).unwrap(), "rad-test-snap");
+    assert!(pool.snap_list().unwrap().iter().any(|snap| snap.id == id));
+
+    let mut buf = vec![0; 16];
+
+    pool.set_read_snapshot(Some(id));
+    let n = pool.read("snap-obj", &mut buf, 0).unwrap();
+    assert_eq!(&buf[..n], b"before");
+
+    pool.set_read_snapshot(None);
+    let n = pool.read("snap-obj", &mut buf, 0).unwrap();
+    assert_eq!(&buf[..n], b"after");
+
+    pool.snap_rollback("snap-obj", "rad-test-snap").unwrap();
+    let n = pool.read("snap-obj", &mut buf, 0).unwrap();
+    assert_eq!(&buf[..n], b"before");
+
+    pool.snap_remove("rad-test-snap").unwrap();
+    pool.remove("snap-obj").unwrap();
+
+    let _ = lock;
+}
+
+
+#[test]
+fn selfmanaged_snapshot() {
+    let lock = CLUSTER_HOLD.lock().unwrap();
+
+    let mut cluster = connect_to_cluster().unwrap();
+
+    // A pool can't have both pool snapshots and self-managed snapshots, so this uses a pool of
+    // its own, created by `run-all-tests.sh`.
+    let mut pool = cluster.get_pool_context("rad-test-selfsnap").unwrap();
+    let mut snapc = SnapContext::new();
+
+    pool.write_full("selfsnap-obj", b"before").unwrap();
+
+    let snap = pool.selfmanaged_snap_create_async().wait().unwrap();
+    snapc.push(snap);
+    pool.set_write_snap_context(&snapc).unwrap();
+
+    pool.write_full("selfsnap-obj", b"after").unwrap();
+
+    let mut buf = vec![0; 16];
+
+    pool.set_read_snapshot(Some(snap));
+    let n = pool.read("selfsnap-obj", &mut buf, 0).unwrap();
+    assert_eq!(&buf[..n], b"before");
+    pool.set_read_snapshot(None);
+
+    pool.selfmanaged_snap_rollback("selfsnap-obj", snap).unwrap();
+    let n = pool.read("selfsnap-obj", &mut buf, 0).unwrap();
+    assert_eq!(&buf[..n], b"before");
+
+    pool.selfmanaged_snap_remove_async(snap).wait().unwrap();
+    pool.remove("selfsnap-obj").unwrap();
+
+    let _ = lock;
+}
@@ -0,0 +1,25 @@
+use futures_executor::block_on;
+
+use super::{CLUSTER_HOLD, connect_to_cluster};
+
+
+#[test]
+fn std_future_read_write() {
+    let lock = CLUSTER_HOLD.lock().unwrap();
+
+    let mut cluster = connect_to_cluster().unwrap();
+    let mut pool = cluster.get_pool_context("rbd").unwrap();
+
+    block_on(pool.write_full_async("std-future-obj", b"awaited")).unwrap();
+
+    assert!(block_on(pool.exists_async("std-future-obj")).unwrap());
+    assert_eq!(block_on(pool.stat_async("std-future-obj")).unwrap().size, 7);
+
+    let (read, buf) = block_on(pool.read_async("std-future-obj", vec![0u8; 7], 0)).unwrap();
+    assert_eq!(read, 7);
+    assert_eq!(&buf[..], b"awaited");
+
+    block_on(pool.remove_async("std-future-obj")).unwrap();
+
+    let _ = lock;
+}
@@ -0,0 +1,49 @@
+use rad::StripeLayout;
+
+use super::{CLUSTER_HOLD, connect_to_cluster};
+
+
+#[test]
+fn striped_object() {
+    let lock = CLUSTER_HOLD.lock().unwrap();
+
+    let mut cluster = connect_to_cluster().unwrap();
+    let mut pool = cluster.get_pool_context("rbd").unwrap();
+
+    let mut striper = pool.striper().unwrap();
+    striper.set_layout(StripeLayout::new(4, 3, 8).unwrap()).unwrap();
+
+    let data = (0..50u8).collect::<Vec<u8>>();
+    striper.write_full("striped-obj", &data).unwrap();
+    striper.append("striped-obj", b"tail").unwrap();
+
+    let stat = striper.stat("striped-obj").unwrap();
+    assert_eq!(stat.size, 54);
+    assert_eq!(stat.layout, StripeLayout::new(4, 3, 8).unwrap());
+
+    // The first set of three objects holds 24 bytes, 8 in each.
+    assert_eq!(pool.stat("striped-obj.0000000000000001").unwrap().size, 8);
+
+    let mut buf = vec![0u8; 64];
+    assert_eq!(striper.read("striped-obj", &mut buf, 0).unwrap(), 54);
+    assert_eq!(&buf[..50], &data[..]);
+    assert_eq!(&buf[50..54], b"tail");
+
+    assert_eq!(striper.read("striped-obj", &mut buf[..10], 45).unwrap(), 9);
+    assert_eq!(&buf[..9], &[45, 46, 47, 48, 49, b't', b'a', b'i', b'l']);
+
+    striper.truncate("striped-obj", 10).unwrap();
+    assert_eq!(striper.stat("striped-obj").unwrap().size, 10);
+    assert!(!pool.exists("striped-obj.0000000000000003").unwrap());
+
+    striper.truncate("striped-obj", 16).unwrap();
+    assert_eq!(striper.read("striped-obj", &mut buf, 0).unwrap(), 16);
+    assert_eq!(&buf[..10], &data[..10]);
+    assert_eq!(&buf[10..16], &[0; 6]);
+
+    striper.remove("striped-obj").unwrap();
+    assert!(!pool.exists("striped-obj.0000000000000000").unwrap());
+    assert!(!pool.exists("striped-obj.0000000000000001").unwrap());
+
+    let _ = lock;
+}
@@ -0,0 +1,47 @@
+use std::time::Duration;
+
+use futures::prelude::*;
+
+use rad::WatchEvent;
+
+use super::{CLUSTER_HOLD, connect_to_cluster};
+
+
+#[test]
+fn watch_notify() {
+    let lock = CLUSTER_HOLD.lock().unwrap();
+
+    let mut cluster = connect_to_cluster().unwrap();
+    let mut pool = cluster.get_pool_context("rbd").unwrap();
+
+    pool.write_full("watched-obj", b"").unwrap();
+
+    let watch = pool.watch("watched-obj").unwrap();
+
+    let response = pool.notify_async("watched-obj", b"invalidate", Duration::from_secs(5))
+        .wait()
+        .unwrap();
+
+    assert_eq!(response.acks.len(), 1);
+    assert!(response.timeouts.is_empty());
+
+    let (event, mut watch) = watch.into_future().wait().map_err(|(error, _)| error).unwrap();
+
+    match event {
+        Some(WatchEvent::Notify { payload, .. }) => assert_eq!(payload, b"invalidate"),
+        other => panic!("expected a notification, got {:?}", other),
+    }
+
+    watch.check().unwrap();
+    watch.rewatch().unwrap();
+
+    let response = pool.notify("watched-obj", b"again", Duration::from_secs(5))
+        .unwrap();
+    assert_eq!(response.acks.len(), 1);
+
+    drop(watch);
+
+    pool.remove("watched-obj").unwrap();
+
+    let _ = lock;
+}
@@ -33,6 +33,12 @@ function start_docker() {
     # The devil's permissions for a total hack
     docker exec $(cat .tmp_tc_name) chmod 666 /etc/ceph/ceph.client.admin.keyring
     
+    echo "Creating the pool for self-managed snapshot tests..."
+
+    # A pool can't have both pool snapshots and self-managed snapshots, so the self-managed
+    # snapshot tests get a pool of their own.
+    docker exec $(cat .tmp_tc_name) ceph osd pool create rad-test-selfsnap 8
+
     echo "Done."
 }
 
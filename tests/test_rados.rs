@@ -4,9 +4,15 @@ extern crate rad;
 #[cfg(feature = "integration-tests")]
 extern crate futures;
 
+#[cfg(feature = "integration-tests")]
+extern crate futures_executor;
+
 #[cfg(feature = "integration-tests")]
 extern crate rand;
 
+#[cfg(feature = "integration-tests")]
+extern crate tokio;
+
 
 #[cfg(feature = "integration-tests")]
 #[macro_use]
@@ -39,6 +45,21 @@ mod integration {
     }
 
 
+    mod async_handle;
+    mod command;
     mod connect;
+    mod copy;
+    mod list_objects;
+    mod locator_key;
+    mod lock;
+    mod namespace;
+    mod object_handle;
+    mod omap;
+    mod operations;
+    mod pool;
     mod read_write_remove;
+    mod snapshot;
+    mod std_future;
+    mod striper;
+    mod watch_notify;
 }